@@ -0,0 +1,31 @@
+#!/bin/bash
+
+# 使用 GStreamer 通过 SRT 接收 weadless compositor 输出流的客户端脚本
+#
+# 使用方法：
+#   ./receive_stream_srt.sh [host] [port] [latency]
+#
+# 默认: 127.0.0.1:5000，延迟 125ms
+# 对应服务端: weadless --output appsrc --protocol srt --srt-mode listener
+# 如果设置了口令，通过 SRT_PASSPHRASE 环境变量传入
+
+HOST=${1:-127.0.0.1}
+PORT=${2:-5000}
+LATENCY=${3:-125}
+
+URI="srt://$HOST:$PORT?mode=caller"
+if [ -n "$SRT_PASSPHRASE" ]; then
+    URI="$URI&passphrase=$SRT_PASSPHRASE"
+fi
+
+echo "正在通过 SRT 接收流，地址: $HOST:$PORT，延迟: ${LATENCY}ms"
+echo "按 Ctrl+C 停止"
+echo ""
+
+gst-launch-1.0 -v \
+    srtsrc uri="$URI" latency=$LATENCY ! \
+    tsdemux ! \
+    h264parse ! \
+    avdec_h264 ! \
+    videoconvert ! \
+    autovideosink sync=false
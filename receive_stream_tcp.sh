@@ -1,6 +1,7 @@
 #!/bin/bash
 
 # 使用 GStreamer 通过 TCP 接收 weadless compositor 输出流的客户端脚本
+# 也可以直接使用 viewer --tcp host:port
 #
 # 使用方法：
 #   ./receive_stream_tcp.sh [host] [port]
@@ -16,7 +17,8 @@ echo ""
 
 gst-launch-1.0 -v \
     tcpclientsrc host=$HOST port=$PORT ! \
-    application/x-rtp,encoding-name=H264,payload=96 ! \
+    application/x-rtp-stream,media=video,clock-rate=90000,encoding-name=H264,payload=96 ! \
+    rtpstreamdepay ! \
     rtph264depay ! \
     h264parse ! \
     avdec_h264 ! \
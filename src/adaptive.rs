@@ -0,0 +1,282 @@
+//! 自适应码率：根据传输反馈调整编码器码率，码率降到下限后可以继续降低帧率或分辨率
+//!
+//! 反馈来源：
+//!   - UDP：rtpbin 收到的 RTCP 接收报告（丢包率、RTT）
+//!   - TCP：tcpserversink 发送队列中积压的数据量
+
+use crate::output::OutputError;
+use crate::rtp::{self, RtcpReport};
+use gst::glib;
+use gst::prelude::*;
+use gst_video::VideoInfo;
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// 检查反馈的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// 丢包率超过该值视为拥塞
+const CONGESTED_LOSS: f64 = 0.10;
+/// 丢包率低于该值视为链路良好
+const GOOD_LOSS: f64 = 0.02;
+/// TCP 发送队列积压超过该时长视为拥塞（秒）
+const CONGESTED_QUEUE_SECS: f64 = 0.5;
+/// TCP 发送队列积压低于该时长视为链路良好（秒）
+const GOOD_QUEUE_SECS: f64 = 0.1;
+/// 拥塞时码率乘以该系数
+const DECREASE_FACTOR: f64 = 0.7;
+
+/// 码率到下限后依次尝试的帧率除数
+const FPS_DIVISORS: [i32; 3] = [1, 2, 4];
+/// 码率到下限后依次尝试的缩放比例
+const SCALE_STEPS: [f64; 3] = [1.0, 0.75, 0.5];
+
+/// 自适应码率选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdaptiveOptions {
+    /// 码率下限（kbps）
+    pub min_bitrate: u32,
+    /// 码率上限（kbps）
+    pub max_bitrate: u32,
+    /// 码率到下限后继续拥塞时降低帧率
+    pub adapt_fps: bool,
+    /// 码率到下限后继续拥塞时降低分辨率
+    pub adapt_scale: bool,
+}
+
+impl AdaptiveOptions {
+    /// 是否需要在编码器前插入 videorate/videoscale
+    pub fn needs_scaler(&self) -> bool {
+        self.adapt_fps || self.adapt_scale
+    }
+}
+
+/// 传输反馈来源
+pub enum Feedback {
+    /// rtpbin，从 RTCP 接收报告中读取丢包率和 RTT
+    Rtcp(gst::Element),
+    /// tcpserversink，读取发送队列积压的字节数
+    TcpQueue(gst::Element),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkState {
+    Congested,
+    Stable,
+    Good,
+}
+
+/// 设置编码器码率（kbps）
+/// 在 pipeline 启动前调用时，vaapih264enc 会切换到 CBR，否则码率设置不生效
+pub fn configure_encoder_bitrate(encoder: &gst::Element, kbps: u32) {
+    if encoder_name(encoder) == "vaapih264enc" {
+        encoder.set_property_from_str("rate-control", "cbr");
+    }
+    set_encoder_bitrate(encoder, kbps);
+}
+
+/// 运行时修改编码器码率（kbps）
+pub fn set_encoder_bitrate(encoder: &gst::Element, kbps: u32) {
+    match encoder_name(encoder).as_str() {
+        // avenc_h264 的码率单位是 bit/s
+        "avenc_h264" => encoder.set_property("bitrate", kbps as i64 * 1000),
+        // vaapih264enc、nvh264enc、x264enc 的码率单位都是 kbit/s
+        _ => encoder.set_property("bitrate", kbps),
+    }
+}
+
+/// 创建 videorate ! videoscale ! capsfilter，用于运行时调整帧率和分辨率
+pub fn make_scaler_elements(video_info: &VideoInfo) -> Result<Vec<gst::Element>, OutputError> {
+    let videorate = gst::ElementFactory::make("videorate")
+        .property("drop-only", true)
+        .build()
+        .map_err(|e| OutputError::element("videorate", e))?;
+    let videoscale = gst::ElementFactory::make("videoscale")
+        .build()
+        .map_err(|e| OutputError::element("videoscale", e))?;
+    let capsfilter = gst::ElementFactory::make("capsfilter")
+        .property("caps", scaled_caps(video_info, 0, 0))
+        .build()
+        .map_err(|e| OutputError::element("capsfilter", e))?;
+    Ok(vec![videorate, videoscale, capsfilter])
+}
+
+/// 启动自适应码率控制线程
+/// capsfilter 为 make_scaler_elements 返回的最后一个元素，未启用帧率/分辨率调整时为 None
+/// 编码器被销毁后线程自动退出
+pub fn spawn_controller(
+    options: AdaptiveOptions,
+    encoder: &gst::Element,
+    capsfilter: Option<&gst::Element>,
+    feedback: Feedback,
+    video_info: VideoInfo,
+    initial_bitrate: u32,
+) {
+    if encoder_name(encoder) == "avenc_h264" {
+        warn!("avenc_h264 不支持运行时修改码率，自适应码率可能不生效");
+    }
+
+    let mut controller = Controller {
+        bitrate: initial_bitrate.clamp(options.min_bitrate, options.max_bitrate),
+        options,
+        encoder: encoder.downgrade(),
+        capsfilter: capsfilter.map(|c| c.downgrade()),
+        video_info,
+        fps_level: 0,
+        scale_level: 0,
+        last_report: None,
+        min_rtt_ms: None,
+    };
+
+    info!(
+        "自适应码率已启用: {}~{} kbps，初始 {} kbps",
+        controller.options.min_bitrate, controller.options.max_bitrate, controller.bitrate
+    );
+
+    thread::spawn(move || loop {
+        thread::sleep(CHECK_INTERVAL);
+        let Some(encoder) = controller.encoder.upgrade() else {
+            break;
+        };
+        if let Some(state) = controller.link_state(&feedback) {
+            controller.step(&encoder, state);
+        }
+    });
+}
+
+struct Controller {
+    options: AdaptiveOptions,
+    encoder: glib::WeakRef<gst::Element>,
+    capsfilter: Option<glib::WeakRef<gst::Element>>,
+    video_info: VideoInfo,
+    /// 当前码率（kbps）
+    bitrate: u32,
+    /// 当前帧率级别（FPS_DIVISORS 的下标）
+    fps_level: usize,
+    /// 当前分辨率级别（SCALE_STEPS 的下标）
+    scale_level: usize,
+    /// 上一次处理过的 RTCP 报告，同一份报告只处理一次
+    last_report: Option<RtcpReport>,
+    /// 观察到的最小 RTT，作为判断排队时延的基准
+    min_rtt_ms: Option<f64>,
+}
+
+impl Controller {
+    /// 读取反馈并判断链路状态，没有新的反馈时返回 None
+    fn link_state(&mut self, feedback: &Feedback) -> Option<LinkState> {
+        match feedback {
+            Feedback::Rtcp(rtpbin) => {
+                let report = rtp::read_rtcp_report(rtpbin)?;
+                if self.last_report == Some(report) {
+                    return None;
+                }
+                self.last_report = Some(report);
+
+                // RTT 明显高于基准说明链路上在排队；没有 RTT 的报告不更新基准，否则基准会一直为 0
+                let queuing = report.rtt_ms.is_some_and(|rtt| {
+                    let min_rtt = self.min_rtt_ms.map_or(rtt, |min_rtt| min_rtt.min(rtt));
+                    self.min_rtt_ms = Some(min_rtt);
+                    rtt > min_rtt * 2.0 + 50.0
+                });
+
+                debug!("自适应码率: {}", report);
+                Some(if report.fraction_lost > CONGESTED_LOSS || queuing {
+                    LinkState::Congested
+                } else if report.fraction_lost < GOOD_LOSS {
+                    LinkState::Good
+                } else {
+                    LinkState::Stable
+                })
+            }
+            Feedback::TcpQueue(sink) => {
+                let queued_bytes = sink.property::<u64>("bytes-queued");
+                let queued_secs = queued_bytes as f64 * 8.0 / (self.bitrate as f64 * 1000.0);
+
+                debug!("自适应码率: TCP 发送队列积压 {} 字节（{:.2} 秒）", queued_bytes, queued_secs);
+                Some(if queued_secs > CONGESTED_QUEUE_SECS {
+                    LinkState::Congested
+                } else if queued_secs < GOOD_QUEUE_SECS {
+                    LinkState::Good
+                } else {
+                    LinkState::Stable
+                })
+            }
+        }
+    }
+
+    /// 根据链路状态调整一步
+    /// 拥塞时先降码率，到下限后依次降帧率、降分辨率；恢复时按相反顺序
+    fn step(&mut self, encoder: &gst::Element, state: LinkState) {
+        match state {
+            LinkState::Congested => {
+                if self.bitrate > self.options.min_bitrate {
+                    let bitrate = (self.bitrate as f64 * DECREASE_FACTOR) as u32;
+                    self.set_bitrate(encoder, bitrate.max(self.options.min_bitrate), "拥塞");
+                } else if self.options.adapt_fps && self.fps_level + 1 < FPS_DIVISORS.len() {
+                    self.fps_level += 1;
+                    self.apply_caps("拥塞");
+                } else if self.options.adapt_scale && self.scale_level + 1 < SCALE_STEPS.len() {
+                    self.scale_level += 1;
+                    self.apply_caps("拥塞");
+                }
+            }
+            LinkState::Good => {
+                if self.scale_level > 0 {
+                    self.scale_level -= 1;
+                    self.apply_caps("恢复");
+                } else if self.fps_level > 0 {
+                    self.fps_level -= 1;
+                    self.apply_caps("恢复");
+                } else if self.bitrate < self.options.max_bitrate {
+                    let bitrate = self.bitrate + (self.bitrate / 10).max(50);
+                    self.set_bitrate(encoder, bitrate.min(self.options.max_bitrate), "恢复");
+                }
+            }
+            LinkState::Stable => {}
+        }
+    }
+
+    fn set_bitrate(&mut self, encoder: &gst::Element, bitrate: u32, reason: &str) {
+        info!("自适应码率（{}）: {} kbps -> {} kbps", reason, self.bitrate, bitrate);
+        self.bitrate = bitrate;
+        set_encoder_bitrate(encoder, bitrate);
+    }
+
+    fn apply_caps(&self, reason: &str) {
+        let Some(capsfilter) = self.capsfilter.as_ref().and_then(|c| c.upgrade()) else {
+            return;
+        };
+        let caps = scaled_caps(&self.video_info, self.fps_level, self.scale_level);
+        info!("自适应码率（{}）: 输出调整为 {}", reason, caps);
+        capsfilter.set_property("caps", caps);
+    }
+}
+
+/// 指定帧率级别和分辨率级别对应的 caps
+fn scaled_caps(video_info: &VideoInfo, fps_level: usize, scale_level: usize) -> gst::Caps {
+    let scale = SCALE_STEPS[scale_level];
+    // 编码器要求宽高为偶数
+    let width = ((video_info.width() as f64 * scale) as i32) & !1;
+    let height = ((video_info.height() as f64 * scale) as i32) & !1;
+    let fps = video_info.fps();
+
+    let caps = gst::Caps::builder("video/x-raw")
+        .field("width", width)
+        .field("height", height);
+    // 可变帧率（0/1）时不限制帧率
+    if fps.numer() == 0 {
+        return caps.build();
+    }
+    caps.field(
+        "framerate",
+        gst::Fraction::new(fps.numer(), fps.denom() * FPS_DIVISORS[fps_level]),
+    )
+    .build()
+}
+
+fn encoder_name(encoder: &gst::Element) -> String {
+    encoder
+        .factory()
+        .map(|factory| factory.name().to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,417 @@
+//! 输出地址和监听地址解析
+//!
+//! 支持以下格式：
+//!   - `127.0.0.1:5000`
+//!   - `[::1]:5000`、`[fe80::1%eth0]:5000`
+//!   - `fe80::1%eth0:5000`（带 zone ID 时可以省略方括号）
+//!   - `example.com:5000`（通过 DNS 解析）
+
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6, ToSocketAddrs};
+
+/// 地址解析错误
+#[derive(Debug)]
+pub enum AddressError {
+    /// 地址为空
+    Empty,
+    /// 缺少端口
+    MissingPort(String),
+    /// 端口不是 1~65535 之间的数字
+    InvalidPort(String),
+    /// IPv6 地址缺少右方括号
+    UnclosedBracket(String),
+    /// 不带 zone ID 的 IPv6 地址没有使用方括号，无法区分地址和端口
+    AmbiguousIpv6(String),
+    /// 既不是 IP 地址也不是合法的主机名
+    InvalidHost(String),
+    /// zone ID 对应的网络接口不存在
+    UnknownZone(String),
+    /// DNS 解析失败
+    Resolve {
+        host: String,
+        source: std::io::Error,
+    },
+    /// DNS 解析没有返回任何地址
+    NoAddress(String),
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::Empty => write!(f, "地址不能为空"),
+            AddressError::MissingPort(addr) => {
+                write!(f, "地址 {} 缺少端口，应为 host:port 或 [ipv6]:port", addr)
+            }
+            AddressError::InvalidPort(port) => {
+                write!(f, "端口 {} 无效，应为 1~65535 之间的数字", port)
+            }
+            AddressError::UnclosedBracket(addr) => write!(f, "地址 {} 缺少 ']'", addr),
+            AddressError::AmbiguousIpv6(addr) => write!(
+                f,
+                "IPv6 地址 {} 有歧义，请使用方括号，例如 [::1]:5000",
+                addr
+            ),
+            AddressError::InvalidHost(host) => write!(f, "无效的主机名或 IP 地址: {}", host),
+            AddressError::UnknownZone(zone) => write!(f, "未知的网络接口（zone ID）: {}", zone),
+            AddressError::Resolve { host, source } => {
+                write!(f, "无法解析主机名 {}: {}", host, source)
+            }
+            AddressError::NoAddress(host) => write!(f, "主机名 {} 没有解析到任何地址", host),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AddressError::Resolve { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// 主机部分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Host {
+    /// IP 地址
+    Ip(IpAddr),
+    /// 带 zone ID 的 IPv6 地址（例如 fe80::1%eth0）
+    ScopedIpv6 {
+        addr: Ipv6Addr,
+        zone: String,
+        scope_id: u32,
+    },
+    /// 需要 DNS 解析的主机名
+    Name(String),
+}
+
+impl Host {
+    /// 解析不带端口的主机部分
+    pub fn parse(s: &str) -> Result<Host, AddressError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(AddressError::Empty);
+        }
+
+        // 允许监听地址也写成 [::1] 的形式
+        let s = match s.strip_prefix('[') {
+            Some(inner) => inner
+                .strip_suffix(']')
+                .ok_or_else(|| AddressError::UnclosedBracket(s.to_string()))?,
+            None => s,
+        };
+
+        if let Some((addr, zone)) = s.split_once('%') {
+            let addr: Ipv6Addr = addr
+                .parse()
+                .map_err(|_| AddressError::InvalidHost(s.to_string()))?;
+            let scope_id = scope_id_for_zone(zone)?;
+            return Ok(Host::ScopedIpv6 {
+                addr,
+                zone: zone.to_string(),
+                scope_id,
+            });
+        }
+
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(Host::Ip(ip));
+        }
+
+        let valid_name = s.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        });
+        if !valid_name || s.len() > 253 {
+            return Err(AddressError::InvalidHost(s.to_string()));
+        }
+
+        Ok(Host::Name(s.to_string()))
+    }
+
+    /// 解析为 IP 地址，主机名会通过 DNS 解析并取第一个结果
+    /// 返回值中的 zone 仅在带 zone ID 的 IPv6 地址时存在
+    fn resolve(&self, port: u16) -> Result<(SocketAddr, Option<String>), AddressError> {
+        match self {
+            Host::Ip(ip) => Ok((SocketAddr::new(*ip, port), None)),
+            Host::ScopedIpv6 {
+                addr,
+                zone,
+                scope_id,
+            } => Ok((
+                SocketAddr::V6(SocketAddrV6::new(*addr, port, 0, *scope_id)),
+                Some(zone.clone()),
+            )),
+            Host::Name(name) => {
+                let addr = (name.as_str(), port)
+                    .to_socket_addrs()
+                    .map_err(|source| AddressError::Resolve {
+                        host: name.clone(),
+                        source,
+                    })?
+                    .next()
+                    .ok_or_else(|| AddressError::NoAddress(name.clone()))?;
+                Ok((addr, None))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Host::Ip(ip) => write!(f, "{}", ip),
+            Host::ScopedIpv6 { addr, zone, .. } => write!(f, "{}%{}", addr, zone),
+            Host::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// 未解析的 host:port 地址
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostPort {
+    pub host: Host,
+    pub port: u16,
+}
+
+impl HostPort {
+    /// 解析 host:port 格式的地址
+    pub fn parse(s: &str) -> Result<HostPort, AddressError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(AddressError::Empty);
+        }
+
+        let (host, port) = if s.starts_with('[') {
+            let end = s
+                .find(']')
+                .ok_or_else(|| AddressError::UnclosedBracket(s.to_string()))?;
+            let port = s[end + 1..]
+                .strip_prefix(':')
+                .ok_or_else(|| AddressError::MissingPort(s.to_string()))?;
+            (&s[..=end], port)
+        } else {
+            let (host, port) = s
+                .rsplit_once(':')
+                .ok_or_else(|| AddressError::MissingPort(s.to_string()))?;
+            // 不带方括号的 IPv6 只有在带 zone ID 时才能确定最后一段是端口
+            if host.contains(':') && !host.contains('%') {
+                return Err(AddressError::AmbiguousIpv6(s.to_string()));
+            }
+            (host, port)
+        };
+
+        let port = match port.parse::<u16>() {
+            Ok(port) if port != 0 => port,
+            _ => return Err(AddressError::InvalidPort(port.to_string())),
+        };
+
+        Ok(HostPort {
+            host: Host::parse(host)?,
+            port,
+        })
+    }
+
+    /// 解析主机名，得到可直接交给 GStreamer 元素使用的地址
+    pub fn resolve(&self) -> Result<ResolvedAddress, AddressError> {
+        let (addr, zone) = self.host.resolve(self.port)?;
+        Ok(ResolvedAddress { addr, zone })
+    }
+}
+
+impl fmt::Display for HostPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.host {
+            Host::Ip(IpAddr::V4(_)) | Host::Name(_) => write!(f, "{}:{}", self.host, self.port),
+            _ => write!(f, "[{}]:{}", self.host, self.port),
+        }
+    }
+}
+
+/// 已解析的地址
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedAddress {
+    pub addr: SocketAddr,
+    /// IPv6 zone ID（网络接口名）
+    pub zone: Option<String>,
+}
+
+impl ResolvedAddress {
+    pub fn ip(&self) -> IpAddr {
+        self.addr.ip()
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// 不带端口的主机字符串（用于 udpsink/tcpserversink 的 host 属性）
+    pub fn host(&self) -> String {
+        match &self.zone {
+            Some(zone) => format!("{}%{}", self.addr.ip(), zone),
+            None => self.addr.ip().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ResolvedAddress {
+    /// IPv6 地址带方括号，可以直接用于 URI
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.addr {
+            SocketAddr::V4(addr) => write!(f, "{}", addr),
+            SocketAddr::V6(_) => write!(f, "[{}]:{}", self.host(), self.port()),
+        }
+    }
+}
+
+/// 解析监听地址（不带端口），主机名会通过 DNS 解析
+/// 返回端口为 0 的 SocketAddr，带 zone ID 的 IPv6 地址保留 scope ID，使用前用 set_port() 设置端口
+pub fn parse_bind_address(s: &str) -> Result<SocketAddr, AddressError> {
+    let host = Host::parse(s)?;
+    let (addr, _) = host.resolve(0)?;
+    Ok(addr)
+}
+
+/// 将 zone ID 转换为 scope ID，支持数字和网络接口名
+fn scope_id_for_zone(zone: &str) -> Result<u32, AddressError> {
+    if zone.is_empty() {
+        return Err(AddressError::UnknownZone(zone.to_string()));
+    }
+    if let Ok(id) = zone.parse::<u32>() {
+        return Ok(id);
+    }
+    if zone.contains('/') || zone.contains("..") {
+        return Err(AddressError::UnknownZone(zone.to_string()));
+    }
+    std::fs::read_to_string(format!("/sys/class/net/{}/ifindex", zone))
+        .ok()
+        .and_then(|index| index.trim().parse().ok())
+        .ok_or_else(|| AddressError::UnknownZone(zone.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ipv4() {
+        let addr = HostPort::parse(" 127.0.0.1:5000 ").unwrap();
+        assert_eq!(addr.host, Host::Ip(IpAddr::from([127, 0, 0, 1])));
+        assert_eq!(addr.port, 5000);
+        assert_eq!(addr.to_string(), "127.0.0.1:5000");
+    }
+
+    #[test]
+    fn parses_bracketed_ipv6() {
+        let addr = HostPort::parse("[::1]:5000").unwrap();
+        assert_eq!(addr.host, Host::Ip(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+        assert_eq!(addr.to_string(), "[::1]:5000");
+    }
+
+    #[test]
+    fn parses_zone_id_with_and_without_brackets() {
+        for s in ["[fe80::1%3]:5000", "fe80::1%3:5000"] {
+            let addr = HostPort::parse(s).unwrap();
+            assert_eq!(
+                addr.host,
+                Host::ScopedIpv6 {
+                    addr: "fe80::1".parse().unwrap(),
+                    zone: "3".to_string(),
+                    scope_id: 3,
+                }
+            );
+            assert_eq!(addr.port, 5000);
+            assert_eq!(addr.to_string(), "[fe80::1%3]:5000");
+
+            let resolved = addr.resolve().unwrap();
+            assert_eq!(resolved.host(), "fe80::1%3");
+            match resolved.addr {
+                SocketAddr::V6(v6) => assert_eq!(v6.scope_id(), 3),
+                SocketAddr::V4(_) => panic!("应为 IPv6 地址"),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unbracketed_ipv6_without_zone() {
+        assert!(matches!(
+            HostPort::parse("::1:5000"),
+            Err(AddressError::AmbiguousIpv6(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        assert!(matches!(HostPort::parse("  "), Err(AddressError::Empty)));
+        assert!(matches!(
+            HostPort::parse("example.com"),
+            Err(AddressError::MissingPort(_))
+        ));
+        assert!(matches!(
+            HostPort::parse("[::1]"),
+            Err(AddressError::MissingPort(_))
+        ));
+        assert!(matches!(
+            HostPort::parse("[::1:5000"),
+            Err(AddressError::UnclosedBracket(_))
+        ));
+        assert!(matches!(
+            HostPort::parse("[fe80::1%]:5000"),
+            Err(AddressError::UnknownZone(_))
+        ));
+        assert!(matches!(
+            HostPort::parse("-bad.example.com:80"),
+            Err(AddressError::InvalidHost(_))
+        ));
+        for port in ["0", "65536", "abc", ""] {
+            assert!(
+                matches!(
+                    HostPort::parse(&format!("127.0.0.1:{}", port)),
+                    Err(AddressError::InvalidPort(_))
+                ),
+                "端口 {:?} 应被拒绝",
+                port
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_hostnames_unresolved() {
+        let addr = HostPort::parse("example.com:80").unwrap();
+        assert_eq!(addr.host, Host::Name("example.com".to_string()));
+        assert_eq!(addr.to_string(), "example.com:80");
+    }
+
+    #[test]
+    fn parses_bind_addresses() {
+        assert_eq!(
+            parse_bind_address("0.0.0.0").unwrap().ip(),
+            IpAddr::from([0, 0, 0, 0])
+        );
+        assert_eq!(
+            parse_bind_address("[::1]").unwrap().ip(),
+            IpAddr::V6(Ipv6Addr::LOCALHOST)
+        );
+        assert_eq!(
+            parse_bind_address("::").unwrap().ip(),
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        );
+    }
+
+    #[test]
+    fn bind_address_keeps_scope_id() {
+        let mut addr = parse_bind_address("fe80::1%3").unwrap();
+        addr.set_port(5900);
+        match addr {
+            SocketAddr::V6(v6) => {
+                assert_eq!(*v6.ip(), "fe80::1".parse::<Ipv6Addr>().unwrap());
+                assert_eq!(v6.port(), 5900);
+                assert_eq!(v6.scope_id(), 3);
+            }
+            SocketAddr::V4(_) => panic!("应该是 IPv6 地址"),
+        }
+    }
+}
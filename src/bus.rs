@@ -0,0 +1,120 @@
+//! GStreamer 总线监控
+//!
+//! 主循环每一帧都会取出总线上积压的消息：错误、警告、延迟变化和 QoS 以结构化字段输出到日志，
+//! 错误会交给调用方决定是否重建 pipeline。
+
+use gst::prelude::*;
+use gst::MessageView;
+use std::fmt;
+use tracing::{debug, error, info, warn};
+
+/// pipeline 上报的错误，出现后 pipeline 无法继续运行
+#[derive(Debug, Clone)]
+pub struct Failure {
+    /// 出错元素的路径
+    pub source: String,
+    pub message: String,
+    /// 错误是否来自编码器（包括编码器内部的子元素）
+    pub from_encoder: bool,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.source.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.source, self.message)
+        }
+    }
+}
+
+/// 取出总线上所有待处理的消息并输出日志
+/// 返回其中的第一个错误
+pub fn drain(pipeline: &gst::Pipeline, encoder: &gst::Element) -> Option<Failure> {
+    let bus = pipeline.bus()?;
+    let mut failure = None;
+    while let Some(message) = bus.pop() {
+        if let Some(error) = handle_message(pipeline, encoder, &message) {
+            failure.get_or_insert(error);
+        }
+    }
+    failure
+}
+
+fn handle_message(
+    pipeline: &gst::Pipeline,
+    encoder: &gst::Element,
+    message: &gst::Message,
+) -> Option<Failure> {
+    let source = message
+        .src()
+        .map(|src| src.path_string().to_string())
+        .unwrap_or_default();
+
+    match message.view() {
+        MessageView::Error(err) => {
+            error!(
+                source = %source,
+                error = %err.error(),
+                debug = err.debug().as_deref().unwrap_or(""),
+                "pipeline 错误"
+            );
+            let from_encoder = message.src().is_some_and(|src| {
+                src == encoder.upcast_ref::<gst::Object>() || src.has_as_ancestor(encoder)
+            });
+            Some(Failure {
+                source,
+                message: err.error().to_string(),
+                from_encoder,
+            })
+        }
+        MessageView::Warning(warning) => {
+            warn!(
+                source = %source,
+                warning = %warning.error(),
+                debug = warning.debug().as_deref().unwrap_or(""),
+                "pipeline 警告"
+            );
+            None
+        }
+        MessageView::Latency(_) => {
+            // 某个元素的延迟发生了变化，需要重新分配整个 pipeline 的延迟
+            if let Err(e) = pipeline.recalculate_latency() {
+                warn!(source = %source, error = %e, "重新计算延迟失败");
+                return None;
+            }
+            let mut query = gst::query::Latency::new();
+            if pipeline.query(&mut query) {
+                let (live, min, max) = query.result();
+                info!(
+                    source = %source,
+                    live,
+                    min_ms = min.mseconds(),
+                    max_ms = max.map(|max| max.mseconds()),
+                    "pipeline 延迟已更新"
+                );
+            }
+            None
+        }
+        MessageView::Qos(qos) => {
+            // 下游处理不过来时由 sink 或编码器上报，频率较高，只在 debug 级别输出
+            let (jitter_ns, proportion, quality) = qos.values();
+            let (processed, dropped) = qos.stats();
+            debug!(
+                source = %source,
+                jitter_ns,
+                proportion,
+                quality,
+                processed = %processed,
+                dropped = %dropped,
+                "QoS"
+            );
+            None
+        }
+        MessageView::Eos(_) => {
+            debug!(source = %source, "pipeline 收到 EOS");
+            None
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,89 @@
+//! 画面变化检测
+//!
+//! wayland-display-core 不提供 damage 信息，这里对整帧数据计算一个廉价的哈希，
+//! 与上一帧比较来判断画面是否变化。计算哈希的开销远小于编码一帧，
+//! 画面空闲时跳过编码可以显著降低 CPU 占用。
+
+use std::time::{Duration, Instant};
+
+/// 计算帧数据的哈希，按 8 字节一组处理
+fn frame_hash(data: &[u8]) -> u64 {
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut chunks = data.chunks_exact(8);
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for chunk in &mut chunks {
+        let word = u64::from_ne_bytes(chunk.try_into().expect("chunk 长度为 8"));
+        hash = (hash ^ word).wrapping_mul(PRIME).rotate_left(29);
+    }
+    for &byte in chunks.remainder() {
+        hash = (hash ^ byte as u64).wrapping_mul(PRIME);
+    }
+    hash
+}
+
+/// 只在画面变化或超过保活间隔时放行帧
+pub struct ChangeGate {
+    keepalive: Duration,
+    last_hash: Option<u64>,
+    last_sent: Option<Instant>,
+}
+
+impl ChangeGate {
+    /// keepalive 为画面不变时两帧之间的最长间隔
+    pub fn new(keepalive: Duration) -> ChangeGate {
+        ChangeGate {
+            keepalive,
+            last_hash: None,
+            last_sent: None,
+        }
+    }
+
+    /// 清除上一帧的记录，下一帧一定会发送（例如暂停取帧后恢复时）
+    pub fn reset(&mut self) {
+        self.last_hash = None;
+        self.last_sent = None;
+    }
+
+    /// 判断这一帧是否需要发送
+    /// 无法读取 buffer 时按画面已变化处理
+    pub fn should_send(&mut self, buffer: &gst::BufferRef) -> bool {
+        let hash = buffer.map_readable().ok().map(|map| frame_hash(map.as_slice()));
+        let changed = hash.is_none() || hash != self.last_hash;
+        self.last_hash = hash;
+
+        let keepalive_due = self
+            .last_sent
+            .is_none_or(|sent| sent.elapsed() >= self.keepalive);
+        if changed || keepalive_due {
+            self.last_sent = Some(Instant::now());
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// 跳过画面时定期请求关键帧，保证新加入的接收端不需要等待太久
+pub struct KeyframeTimer {
+    interval: Duration,
+    last: Instant,
+}
+
+impl KeyframeTimer {
+    pub fn new(interval: Duration) -> KeyframeTimer {
+        KeyframeTimer {
+            interval,
+            last: Instant::now(),
+        }
+    }
+
+    /// 距离上一次请求关键帧已经超过间隔时返回 true，并重新计时
+    pub fn due(&mut self) -> bool {
+        if self.last.elapsed() >= self.interval {
+            self.last = Instant::now();
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! 客户端计数，用于按需取帧和编码
+//!
+//! 连接和断开通知来自 VNC 服务器的事件循环、tcpserversink 的 client-added/client-removed 信号，
+//! 以及 listener 模式下 srtsink 的 caller-added/caller-removed 信号。
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::info;
+
+/// 当前连接的客户端数量，可以在线程之间共享
+#[derive(Debug, Clone, Default)]
+pub struct ClientTracker {
+    count: Arc<AtomicUsize>,
+}
+
+impl ClientTracker {
+    pub fn new() -> ClientTracker {
+        ClientTracker::default()
+    }
+
+    /// 记录一个客户端连接
+    pub fn connected(&self) {
+        let count = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        info!("当前客户端数: {}", count);
+    }
+
+    /// 记录一个客户端断开
+    pub fn disconnected(&self) {
+        // 不会减到 0 以下，避免重复的断开通知导致计数错误
+        let previous = self
+            .count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                Some(count.saturating_sub(1))
+            })
+            .unwrap_or(0);
+        info!("当前客户端数: {}", previous.saturating_sub(1));
+    }
+
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+}
+
+/// 按需取帧的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Demand {
+    /// 没有客户端，跳过取帧和编码
+    Idle,
+    /// 刚刚有客户端连接，需要立即发送关键帧
+    Resumed,
+    /// 有客户端连接，正常取帧
+    Active,
+}
+
+/// 根据客户端数量决定是否取帧
+pub struct OnDemand {
+    clients: ClientTracker,
+    active: bool,
+}
+
+impl OnDemand {
+    pub fn new(clients: ClientTracker) -> OnDemand {
+        info!("按需取帧已启用，等待客户端连接...");
+        OnDemand {
+            clients,
+            active: false,
+        }
+    }
+
+    /// 检查客户端数量，在状态变化时输出日志
+    pub fn poll(&mut self) -> Demand {
+        let has_clients = self.clients.count() > 0;
+        match (self.active, has_clients) {
+            (false, false) => Demand::Idle,
+            (false, true) => {
+                info!("客户端已连接，恢复取帧和编码");
+                self.active = true;
+                Demand::Resumed
+            }
+            (true, true) => Demand::Active,
+            (true, false) => {
+                info!("没有客户端连接，暂停取帧和编码");
+                self.active = false;
+                Demand::Idle
+            }
+        }
+    }
+}
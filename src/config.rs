@@ -0,0 +1,269 @@
+//! 配置文件：把 TOML/YAML 配置转换为命令行参数
+//!
+//! 配置项的名称与命令行参数的长名称相同（不带 `--`），例如：
+//!
+//! ```toml
+//! width = 1280
+//! height = 720
+//! fps = "30"
+//! output = "appsrc"
+//!
+//! # 按输出分组的设置，键名可以省略分组前缀（[vnc] 中的 port 对应 --vnc-port）
+//! [appsrc]
+//! protocol = "tcp"
+//! output-address = "0.0.0.0:5000"
+//!
+//! [vnc]
+//! port = 5901
+//!
+//! # 命名配置，通过 --profile low-bandwidth 选择，覆盖上面的设置
+//! [profiles.low-bandwidth]
+//! fps = "15"
+//! [profiles.low-bandwidth.appsrc]
+//! bitrate = 800
+//! ```
+//!
+//! 转换出的参数放在真正的命令行参数之前，命令行上的同名参数会覆盖配置文件中的值。
+
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// 按输出分组的配置节，以及节内键名对应的参数前缀
+const SECTIONS: [(&str, &str); 6] = [
+    ("appsrc", ""),
+    ("vnc", "vnc-"),
+    ("rtsp", "rtsp-"),
+    ("srt", "srt-"),
+    ("udp", "udp-"),
+    ("input", "input-"),
+];
+
+/// 配置文件错误
+#[derive(Debug)]
+pub enum ConfigError {
+    /// 无法读取文件
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// 文件格式错误
+    Parse { path: PathBuf, message: String },
+    /// 扩展名不是 .toml、.yaml 或 .yml
+    UnknownFormat(PathBuf),
+    /// 没有对应的命令行参数
+    UnknownKey(String),
+    /// 值的类型无法转换为参数
+    InvalidValue { key: String, message: String },
+    /// 指定的命名配置不存在
+    UnknownProfile(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => {
+                write!(f, "无法读取配置文件 {}: {}", path.display(), source)
+            }
+            ConfigError::Parse { path, message } => {
+                write!(f, "配置文件 {} 格式错误: {}", path.display(), message)
+            }
+            ConfigError::UnknownFormat(path) => write!(
+                f,
+                "无法识别配置文件 {} 的格式，扩展名应为 .toml、.yaml 或 .yml",
+                path.display()
+            ),
+            ConfigError::UnknownKey(key) => write!(f, "未知的配置项: {}", key),
+            ConfigError::InvalidValue { key, message } => {
+                write!(f, "配置项 {} 的值无效: {}", key, message)
+            }
+            ConfigError::UnknownProfile(name) => {
+                write!(f, "配置文件中没有名为 {} 的 profile", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// 读取配置文件，转换为 command 能够解析的参数列表（不含程序名）
+/// profile 为 Some 时，profiles 中对应的设置会覆盖顶层设置
+pub fn config_args(
+    path: &Path,
+    profile: Option<&str>,
+    command: &clap::Command,
+) -> Result<Vec<OsString>, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut table = parse(path, &text)?;
+
+    let profiles = match table.remove("profiles") {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => {
+            return Err(ConfigError::InvalidValue {
+                key: "profiles".to_string(),
+                message: "应为表".to_string(),
+            })
+        }
+        None => toml::Table::new(),
+    };
+
+    let mut args = Vec::new();
+    push_table(&table, command, &mut args)?;
+    if let Some(name) = profile {
+        match profiles.get(name) {
+            Some(toml::Value::Table(profile)) => push_table(profile, command, &mut args)?,
+            _ => return Err(ConfigError::UnknownProfile(name.to_string())),
+        }
+    }
+    Ok(args)
+}
+
+/// 按扩展名解析 TOML 或 YAML
+fn parse(path: &Path, text: &str) -> Result<toml::Table, ConfigError> {
+    let parse_error = |message: String| ConfigError::Parse {
+        path: path.to_path_buf(),
+        message,
+    };
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(text).map_err(|e| parse_error(e.to_string())),
+        // YAML 也转换为 toml::Table，两种格式使用同一套处理逻辑
+        Some("yaml" | "yml") => {
+            serde_yaml::from_str(text).map_err(|e| parse_error(e.to_string()))
+        }
+        _ => Err(ConfigError::UnknownFormat(path.to_path_buf())),
+    }
+}
+
+/// 把一个表（顶层或 profile）中的设置转换为参数
+fn push_table(
+    table: &toml::Table,
+    command: &clap::Command,
+    args: &mut Vec<OsString>,
+) -> Result<(), ConfigError> {
+    for (key, value) in table {
+        if let Some((_, prefix)) = SECTIONS.iter().find(|(section, _)| *section == key.as_str()) {
+            let toml::Value::Table(section) = value else {
+                return Err(ConfigError::InvalidValue {
+                    key: key.clone(),
+                    message: "应为表".to_string(),
+                });
+            };
+            for (name, value) in section {
+                // 先尝试带分组前缀的参数名，再尝试原名
+                let prefixed = format!("{}{}", prefix, name);
+                let arg = find_arg(command, &prefixed)
+                    .or_else(|| find_arg(command, name))
+                    .ok_or_else(|| ConfigError::UnknownKey(format!("{}.{}", key, name)))?;
+                push_arg(arg, value, args)?;
+            }
+            continue;
+        }
+
+        let arg = find_arg(command, key).ok_or_else(|| ConfigError::UnknownKey(key.clone()))?;
+        push_arg(arg, value, args)?;
+    }
+    Ok(())
+}
+
+/// 让开关参数同时接受 --flag 和 --flag=true/false，命令行可以关闭配置文件中打开的开关
+/// 解析配置文件和命令行参数的 Command 都需要经过这一步
+pub fn switch_values(command: clap::Command) -> clap::Command {
+    command.mut_args(|arg| {
+        if !matches!(arg.get_action(), clap::ArgAction::SetTrue) {
+            return arg;
+        }
+        arg.action(clap::ArgAction::Set)
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("true")
+            .default_value("false")
+            .value_name("BOOL")
+            .hide_default_value(true)
+            .hide_possible_values(true)
+    })
+}
+
+/// 按长名称查找参数，同时接受下划线写法（render_node）
+fn find_arg<'a>(command: &'a clap::Command, name: &str) -> Option<&'a clap::Arg> {
+    let name = name.replace('_', "-");
+    command
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(name.as_str()))
+}
+
+fn push_arg(
+    arg: &clap::Arg,
+    value: &toml::Value,
+    args: &mut Vec<OsString>,
+) -> Result<(), ConfigError> {
+    let long = arg.get_long().expect("只查找带长名称的参数");
+    let invalid = |message: &str| ConfigError::InvalidValue {
+        key: long.to_string(),
+        message: message.to_string(),
+    };
+
+    // 开关参数经过 switch_values() 处理，false 也会写成 --flag=false
+    let value = match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Integer(i) => i.to_string(),
+        toml::Value::Float(f) => f.to_string(),
+        toml::Value::Boolean(b) => b.to_string(),
+        // 列表按逗号连接，例如 udp-clients = ["10.0.0.2:5000", "10.0.0.3:5000"]
+        toml::Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                toml::Value::String(s) => Ok(s.clone()),
+                toml::Value::Integer(i) => Ok(i.to_string()),
+                _ => Err(invalid("列表中只能包含字符串或整数")),
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .join(","),
+        toml::Value::Datetime(_) | toml::Value::Table(_) => {
+            return Err(invalid("不支持的值类型"))
+        }
+    };
+    args.push(format!("--{}={}", long, value).into());
+    Ok(())
+}
+
+/// 以 TOML 格式输出生效的配置，输出可以直接作为配置文件使用
+/// skip 中的参数（例如 --config 本身）不输出
+pub fn print_effective(command: &clap::Command, matches: &clap::ArgMatches, skip: &[&str]) {
+    for arg in command.get_arguments() {
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        let builtin = matches!(
+            arg.get_action(),
+            clap::ArgAction::Help | clap::ArgAction::Version
+        );
+        if builtin || skip.contains(&long) {
+            continue;
+        }
+        let values: Vec<String> = matches
+            .get_raw(arg.get_id().as_str())
+            .map(|values| values.map(|v| v.to_string_lossy().into_owned()).collect())
+            .unwrap_or_default();
+        if values.is_empty() {
+            println!("# {} =", long);
+            continue;
+        }
+        let value = values.join(",");
+        let literal = if value == "true" || value == "false" || value.parse::<i64>().is_ok() {
+            value
+        } else {
+            toml::Value::String(value).to_string()
+        };
+        println!("{} = {}", long, literal);
+    }
+}
@@ -0,0 +1,180 @@
+//! 输出裁剪：只输出画面中的一个矩形区域
+//!
+//! GStreamer 输出在 appsrc 之后插入 videocrop，VNC 输出在转换为 RGB888 时只取裁剪区域。
+//! 裁剪在缩放之前进行，两者可以一起使用。裁剪区域可以在运行中修改，见 ReloadHandle::crop。
+
+use gst::prelude::*;
+use std::fmt;
+
+/// 裁剪区域（像素）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CropRect {
+    /// 检查区域是否在画面内；even 为 true 时要求位置和尺寸都是偶数（编码器和 YUV 格式需要）
+    pub fn validate(&self, width: u32, height: u32, even: bool) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err(format!("裁剪区域 {} 为空", self));
+        }
+        if self.x.saturating_add(self.width) > width || self.y.saturating_add(self.height) > height
+        {
+            return Err(format!("裁剪区域 {} 超出画面 {}x{}", self, width, height));
+        }
+        if even
+            && [self.x, self.y, self.width, self.height]
+                .iter()
+                .any(|v| v % 2 != 0)
+        {
+            return Err(format!("裁剪区域 {} 的位置和尺寸必须为偶数", self));
+        }
+        Ok(())
+    }
+
+    /// videocrop 的 left、right、top、bottom
+    fn margins(&self, width: u32, height: u32) -> (i32, i32, i32, i32) {
+        (
+            self.x as i32,
+            (width - self.x - self.width) as i32,
+            self.y as i32,
+            (height - self.y - self.height) as i32,
+        )
+    }
+}
+
+/// 与 X11 geometry 相同的写法：WIDTHxHEIGHT+X+Y
+impl fmt::Display for CropRect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+/// 解析 WIDTHxHEIGHT+X+Y（例如 1280x720+320+180），用于命令行参数
+pub fn parse_crop(s: &str) -> Result<CropRect, String> {
+    let invalid = || {
+        format!(
+            "裁剪区域格式应为 WIDTHxHEIGHT+X+Y，例如 1280x720+0+0: {}",
+            s
+        )
+    };
+    let mut parts = s.trim().split('+');
+    let size = parts.next().ok_or_else(invalid)?;
+    let (width, height) = size.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let x = parts.next().unwrap_or("0");
+    let y = parts.next().unwrap_or("0");
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    let parse = |v: &str| v.trim().parse::<u32>().map_err(|_| invalid());
+    Ok(CropRect {
+        x: parse(x)?,
+        y: parse(y)?,
+        width: parse(width)?,
+        height: parse(height)?,
+    })
+}
+
+/// 创建 videocrop
+pub fn make_videocrop(
+    crop: &CropRect,
+    width: u32,
+    height: u32,
+) -> Result<gst::Element, gst::glib::BoolError> {
+    let videocrop = gst::ElementFactory::make("videocrop").build()?;
+    set_videocrop(&videocrop, crop, width, height);
+    Ok(videocrop)
+}
+
+/// 修改 videocrop 的裁剪区域，pipeline 运行中也可以调用
+/// width、height 为 videocrop 输入画面的尺寸
+pub fn set_videocrop(videocrop: &gst::Element, crop: &CropRect, width: u32, height: u32) {
+    let (left, right, top, bottom) = crop.margins(width, height);
+    videocrop.set_property("left", left);
+    videocrop.set_property("right", right);
+    videocrop.set_property("top", top);
+    videocrop.set_property("bottom", bottom);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(width: u32, height: u32, x: u32, y: u32) -> CropRect {
+        CropRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn parses_geometry() {
+        assert_eq!(
+            parse_crop("1280x720+320+180"),
+            Ok(rect(1280, 720, 320, 180))
+        );
+        assert_eq!(parse_crop(" 640X480+0+0 "), Ok(rect(640, 480, 0, 0)));
+        assert_eq!(
+            parse_crop("1280x720+320+180").unwrap().to_string(),
+            "1280x720+320+180"
+        );
+    }
+
+    #[test]
+    fn position_defaults_to_origin() {
+        assert_eq!(parse_crop("1280x720"), Ok(rect(1280, 720, 0, 0)));
+        assert_eq!(parse_crop("1280x720+16"), Ok(rect(1280, 720, 16, 0)));
+    }
+
+    #[test]
+    fn rejects_malformed_geometry() {
+        for s in [
+            "",
+            "1280",
+            "1280x",
+            "x720+0+0",
+            "1280x720+0+0+0",
+            "1280x720-10+0",
+            "1280x720+a+0",
+        ] {
+            assert!(parse_crop(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn validates_region_inside_frame() {
+        assert!(rect(1920, 1080, 0, 0).validate(1920, 1080, true).is_ok());
+        assert!(rect(1280, 720, 640, 360).validate(1920, 1080, true).is_ok());
+        assert!(rect(1280, 720, 641, 360)
+            .validate(1920, 1080, false)
+            .is_err());
+        assert!(rect(1280, 720, 0, 361).validate(1920, 1080, false).is_err());
+        assert!(rect(0, 720, 0, 0).validate(1920, 1080, false).is_err());
+        assert!(rect(1280, 0, 0, 0).validate(1920, 1080, false).is_err());
+        // 溢出时不会绕回
+        assert!(rect(2, 2, u32::MAX, 0).validate(1920, 1080, false).is_err());
+    }
+
+    #[test]
+    fn even_check_covers_position_and_size() {
+        assert!(rect(641, 360, 0, 0).validate(1920, 1080, false).is_ok());
+        assert!(rect(641, 360, 0, 0).validate(1920, 1080, true).is_err());
+        assert!(rect(640, 361, 0, 0).validate(1920, 1080, true).is_err());
+        assert!(rect(640, 360, 1, 0).validate(1920, 1080, true).is_err());
+        assert!(rect(640, 360, 0, 1).validate(1920, 1080, true).is_err());
+    }
+
+    #[test]
+    fn margins_match_videocrop_properties() {
+        assert_eq!(
+            rect(1280, 720, 320, 180).margins(1920, 1080),
+            (320, 320, 180, 180)
+        );
+        assert_eq!(rect(1920, 1080, 0, 0).margins(1920, 1080), (0, 0, 0, 0));
+    }
+}
@@ -0,0 +1,120 @@
+//! H.264 编码器选择
+//!
+//! 编码器按优先级排列：硬件编码器优先，软件编码器兜底。
+//! 某个编码器在运行时出错（例如 GPU 被占用、驱动异常）时，重建 pipeline 会从列表中的下一个开始尝试。
+
+use gst::glib;
+use gst::prelude::*;
+use std::fmt;
+use tracing::info;
+
+/// 按优先级排列的 H.264 编码器
+pub const ENCODERS: [&str; 4] = ["vaapih264enc", "nvh264enc", "x264enc", "avenc_h264"];
+
+/// 编码器错误
+#[derive(Debug)]
+pub enum EncoderError {
+    /// 没有安装任何 H.264 编码器
+    NotFound,
+    /// 指定编码器之后的编码器都不可用，无法继续退回
+    NoFallback(&'static str),
+    /// 插件存在但无法创建元素
+    Create {
+        name: &'static str,
+        source: glib::BoolError,
+    },
+}
+
+impl EncoderError {
+    /// 给用户的处理建议
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            EncoderError::NotFound | EncoderError::NoFallback(_) => Some(
+                "请安装以下插件之一：\n\
+                - gstreamer1.0-plugins-good (x264enc)\n\
+                - gstreamer1.0-plugins-bad (avenc_h264)\n\
+                - gstreamer1.0-plugins-bad (vaapih264enc, 需要硬件支持)\n\
+                - gstreamer1.0-plugins-bad (nvh264enc, 需要 NVIDIA GPU)"
+                    .to_string(),
+            ),
+            EncoderError::Create { name, .. } => Some(format!(
+                "使用 gst-inspect-1.0 {} 检查插件是否完整，或者卸载该插件改用其他编码器",
+                name
+            )),
+        }
+    }
+}
+
+impl fmt::Display for EncoderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncoderError::NotFound => write!(f, "未找到可用的 H.264 编码器"),
+            EncoderError::NoFallback(name) => {
+                write!(f, "{} 之后没有其他可用的 H.264 编码器", name)
+            }
+            EncoderError::Create { name, source } => write!(f, "无法创建 {}: {}", name, source),
+        }
+    }
+}
+
+impl std::error::Error for EncoderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EncoderError::Create { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// 从优先级列表的第 start 个开始，创建第一个可用的编码器
+/// 返回编码器在列表中的位置和编码器元素
+pub fn make_encoder(start: usize) -> Result<(usize, gst::Element), EncoderError> {
+    for (index, &name) in ENCODERS.iter().enumerate().skip(start) {
+        if gst::ElementFactory::find(name).is_none() {
+            continue;
+        }
+        return Ok((index, build_encoder(name)?));
+    }
+
+    match start {
+        0 => Err(EncoderError::NotFound),
+        _ => Err(EncoderError::NoFallback(ENCODERS[start - 1])),
+    }
+}
+
+/// 编码器的 sink pad 是否能直接接受 caps（用于判断能否省去 videoconvert）
+pub fn accepts(encoder: &gst::Element, caps: &gst::Caps) -> bool {
+    encoder
+        .static_pad("sink")
+        .is_some_and(|pad| pad.pad_template_caps().can_intersect(caps))
+}
+
+fn build_encoder(name: &'static str) -> Result<gst::Element, EncoderError> {
+    let builder = gst::ElementFactory::make(name);
+    let builder = match name {
+        "vaapih264enc" => {
+            info!("使用 vaapih264enc（硬件加速）");
+            builder.property("tune", "low-power")
+        }
+        "nvh264enc" => {
+            info!("使用 nvh264enc（NVIDIA 硬件加速）");
+            // nvh264enc 的属性需要枚举类型，这里使用默认配置
+            // 默认配置已经针对低延迟进行了优化
+            builder
+        }
+        "x264enc" => {
+            info!("使用 x264enc（软件编码）");
+            builder
+                .property("tune", "zerolatency")
+                .property("speed-preset", "ultrafast")
+        }
+        "avenc_h264" => {
+            info!("使用 avenc_h264（软件编码）");
+            builder.property("preset", "ultrafast")
+        }
+        _ => builder,
+    };
+    builder
+        .build()
+        .map_err(|source| EncoderError::Create { name, source })
+}
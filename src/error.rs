@@ -0,0 +1,175 @@
+//! 程序级错误：合成器（display）错误，以及按错误类别区分的进程退出码
+
+use crate::input::InputError;
+use crate::output::OutputError;
+use crate::vnc::VncError;
+use gst::glib;
+use std::fmt;
+
+/// 其他错误
+pub const EXIT_FAILURE: i32 = 1;
+/// 参数或配置无效
+pub const EXIT_CONFIG: i32 = 2;
+/// 无法创建合成器或测试图案
+pub const EXIT_DISPLAY: i32 = 3;
+/// 没有可用的编码器
+pub const EXIT_ENCODER: i32 = 4;
+/// 无法启动输出流
+pub const EXIT_OUTPUT: i32 = 5;
+/// 无法启动 VNC 服务器
+pub const EXIT_VNC: i32 = 6;
+/// 无法启动输入通道
+pub const EXIT_INPUT: i32 = 7;
+
+/// 合成器错误
+#[derive(Debug)]
+pub enum DisplayError {
+    /// 无法创建 WaylandDisplay
+    Create(String),
+    /// 无法启动测试图案
+    TestPattern(String),
+    /// 合成器正在关闭，不再产生新的帧
+    Flushing,
+    /// 合成器已经结束
+    Eos,
+    /// 获取帧失败，可以重试
+    Frame(gst::FlowError),
+}
+
+impl DisplayError {
+    /// 是否表示合成器正在关闭（而不是真正的错误）
+    pub fn is_shutdown(&self) -> bool {
+        matches!(self, DisplayError::Flushing | DisplayError::Eos)
+    }
+}
+
+impl From<gst::FlowError> for DisplayError {
+    fn from(e: gst::FlowError) -> DisplayError {
+        match e {
+            gst::FlowError::Flushing => DisplayError::Flushing,
+            gst::FlowError::Eos => DisplayError::Eos,
+            e => DisplayError::Frame(e),
+        }
+    }
+}
+
+impl fmt::Display for DisplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayError::Create(e) => write!(f, "无法创建 WaylandDisplay: {}", e),
+            DisplayError::TestPattern(e) => write!(f, "无法启动测试图案: {}", e),
+            DisplayError::Flushing => write!(f, "合成器正在关闭"),
+            DisplayError::Eos => write!(f, "合成器已结束"),
+            DisplayError::Frame(e) => write!(f, "获取帧失败: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for DisplayError {}
+
+/// 导致程序退出的错误
+#[derive(Debug)]
+pub enum AppError {
+    /// 无法初始化 GStreamer
+    Init(glib::Error),
+    /// 参数无效
+    Config(String),
+    Display(DisplayError),
+    Output(OutputError),
+    Vnc(VncError),
+    Input(InputError),
+}
+
+impl AppError {
+    /// 进程退出码，脚本可以据此区分失败原因
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::Init(_) => EXIT_FAILURE,
+            AppError::Config(_) => EXIT_CONFIG,
+            AppError::Display(_) => EXIT_DISPLAY,
+            AppError::Output(OutputError::Encoder(_)) => EXIT_ENCODER,
+            AppError::Output(OutputError::Address { .. } | OutputError::InvalidOption(_)) => {
+                EXIT_CONFIG
+            }
+            AppError::Output(_) => EXIT_OUTPUT,
+            AppError::Vnc(VncError::UnsupportedFormat(_) | VncError::InvalidOption(_)) => {
+                EXIT_CONFIG
+            }
+            AppError::Vnc(_) => EXIT_VNC,
+            AppError::Input(InputError::Address { .. }) => EXIT_CONFIG,
+            AppError::Input(InputError::Io { .. }) => EXIT_INPUT,
+        }
+    }
+
+    /// 给用户的处理建议
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            AppError::Init(_) => {
+                Some("检查 GStreamer 是否已正确安装（gst-inspect-1.0 --version）".to_string())
+            }
+            AppError::Config(_) => None,
+            AppError::Display(DisplayError::Create(_)) => Some(
+                "如果使用软件渲染，请使用 --render-node software；\
+                使用硬件加速时确认 /dev/dri/renderD* 存在且有访问权限"
+                    .to_string(),
+            ),
+            AppError::Display(DisplayError::TestPattern(_)) => {
+                Some("测试图案需要 videotestsrc，请安装 gstreamer1.0-plugins-base".to_string())
+            }
+            AppError::Display(_) => None,
+            AppError::Output(e) => e.hint(),
+            AppError::Vnc(e) => e.hint(),
+            AppError::Input(InputError::Address { .. }) => Some(
+                "地址格式为 host:port，例如 127.0.0.1:5001、[::1]:5001 或 [fe80::1%eth0]:5001"
+                    .to_string(),
+            ),
+            AppError::Input(InputError::Io { .. }) => {
+                Some("端口可能已被占用，请换用 --input-listen 的端口".to_string())
+            }
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Init(e) => write!(f, "无法初始化 GStreamer: {}", e),
+            AppError::Config(e) => write!(f, "{}", e),
+            AppError::Display(e) => write!(f, "{}", e),
+            AppError::Output(e) => write!(f, "无法启动输出流: {}", e),
+            AppError::Vnc(e) => write!(f, "无法启动 VNC 服务器: {}", e),
+            AppError::Input(e) => write!(f, "无法启动输入通道: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Init(e) => Some(e),
+            AppError::Config(_) => None,
+            AppError::Display(e) => Some(e),
+            AppError::Output(e) => Some(e),
+            AppError::Vnc(e) => Some(e),
+            AppError::Input(e) => Some(e),
+        }
+    }
+}
+
+impl From<OutputError> for AppError {
+    fn from(e: OutputError) -> AppError {
+        AppError::Output(e)
+    }
+}
+
+impl From<VncError> for AppError {
+    fn from(e: VncError) -> AppError {
+        AppError::Vnc(e)
+    }
+}
+
+impl From<InputError> for AppError {
+    fn from(e: InputError) -> AppError {
+        AppError::Input(e)
+    }
+}
@@ -0,0 +1,56 @@
+//! 合成器输出的像素格式
+
+use gst_video::VideoFormat;
+use std::fmt;
+
+/// 命令行和配置文件中可选的像素格式
+/// 合成器只输出四种 32 位 RGB 格式，其他格式在 appsrc 输出中多经过一次 videoconvert 转换后交给编码器，
+/// 只用于满足编码器或下游的格式要求，不是性能更好的路径
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PixelFormat {
+    #[value(name = "RGBx")]
+    Rgbx,
+    #[value(name = "RGBA")]
+    Rgba,
+    #[value(name = "BGRx")]
+    Bgrx,
+    #[value(name = "BGRA")]
+    Bgra,
+    /// 每个像素 3 字节
+    #[value(name = "RGB")]
+    Rgb,
+    /// Y 平面加交错的 UV 平面，硬件编码器的常用输入格式
+    #[value(name = "NV12")]
+    Nv12,
+    /// Y、U、V 三个平面，x264enc 的默认输入格式
+    #[value(name = "I420")]
+    I420,
+}
+
+impl PixelFormat {
+    pub fn video_format(self) -> VideoFormat {
+        match self {
+            PixelFormat::Rgbx => VideoFormat::Rgbx,
+            PixelFormat::Rgba => VideoFormat::Rgba,
+            PixelFormat::Bgrx => VideoFormat::Bgrx,
+            PixelFormat::Bgra => VideoFormat::Bgra,
+            PixelFormat::Rgb => VideoFormat::Rgb,
+            PixelFormat::Nv12 => VideoFormat::Nv12,
+            PixelFormat::I420 => VideoFormat::I420,
+        }
+    }
+}
+
+/// 合成器实际输出的格式：RGBx、RGBA、BGRx、BGRA 直接输出，其他格式输出 RGBx
+pub fn compositor_format(format: VideoFormat) -> VideoFormat {
+    match format {
+        VideoFormat::Rgbx | VideoFormat::Rgba | VideoFormat::Bgrx | VideoFormat::Bgra => format,
+        _ => VideoFormat::Rgbx,
+    }
+}
+
+impl fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.video_format().to_str())
+    }
+}
@@ -0,0 +1,109 @@
+//! 帧率解析
+//!
+//! 支持以下写法：
+//!   - 整数：`60`
+//!   - 分数：`60000/1001`
+//!   - 小数：`0.5`、`29.97`（29.97、59.94、23.976 等 NTSC 帧率会换算为 N*1000/1001）
+
+/// 允许的最大帧率
+const MAX_FPS: f64 = 1000.0;
+
+/// 解析帧率，用作 clap 的 value_parser
+pub fn parse_frame_rate(s: &str) -> Result<gst::Fraction, String> {
+    let (numer, denom) = parse_ratio(s)?;
+    Ok(gst::Fraction::new(numer, denom))
+}
+
+/// 解析帧率为约分后的分子和分母
+fn parse_ratio(s: &str) -> Result<(i32, i32), String> {
+    let s = s.trim();
+    let (numer, denom) = match s.split_once('/') {
+        Some((numer, denom)) => {
+            let numer: i32 = numer
+                .trim()
+                .parse()
+                .map_err(|_| format!("无效的帧率: {}", s))?;
+            let denom: i32 = denom
+                .trim()
+                .parse()
+                .map_err(|_| format!("无效的帧率: {}", s))?;
+            if denom <= 0 {
+                return Err(format!("帧率的分母必须大于 0: {}", s));
+            }
+            (numer, denom)
+        }
+        None => {
+            let fps: f64 = s.parse().map_err(|_| format!("无效的帧率: {}", s))?;
+            if !fps.is_finite() {
+                return Err(format!("无效的帧率: {}", s));
+            }
+            decimal_to_ratio(fps)
+        }
+    };
+
+    if numer <= 0 {
+        return Err(format!("帧率必须大于 0: {}", s));
+    }
+    if numer as f64 / denom as f64 > MAX_FPS {
+        return Err(format!("帧率不能超过 {} fps: {}", MAX_FPS, s));
+    }
+
+    let divisor = gcd(numer, denom);
+    Ok((numer / divisor, denom / divisor))
+}
+
+/// 小数帧率转换为分数，精确到 0.001 fps
+fn decimal_to_ratio(fps: f64) -> (i32, i32) {
+    // NTSC 帧率是整数帧率的 1000/1001
+    let ntsc = (fps * 1.001).round();
+    if ntsc >= 1.0 && fps.fract() != 0.0 && (fps - ntsc / 1.001).abs() < 0.005 {
+        return (ntsc as i32 * 1000, 1001);
+    }
+    ((fps.min(MAX_FPS + 1.0) * 1000.0).round() as i32, 1000)
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        a.abs().max(1)
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_integers_and_fractions() {
+        assert_eq!(parse_ratio("60"), Ok((60, 1)));
+        assert_eq!(parse_ratio(" 60000/1001 "), Ok((60000, 1001)));
+        assert_eq!(parse_ratio("30 / 2"), Ok((15, 1)));
+    }
+
+    #[test]
+    fn detects_ntsc_rates() {
+        assert_eq!(parse_ratio("29.97"), Ok((30000, 1001)));
+        assert_eq!(parse_ratio("59.94"), Ok((60000, 1001)));
+        assert_eq!(parse_ratio("23.976"), Ok((24000, 1001)));
+        assert_eq!(parse_ratio("119.88"), Ok((120000, 1001)));
+    }
+
+    #[test]
+    fn keeps_other_decimals_exact() {
+        assert_eq!(parse_ratio("0.5"), Ok((1, 2)));
+        assert_eq!(parse_ratio("25.5"), Ok((51, 2)));
+        // 整数不按 NTSC 换算
+        assert_eq!(parse_ratio("30.0"), Ok((30, 1)));
+    }
+
+    #[test]
+    fn rejects_invalid_rates() {
+        for s in [
+            "", "abc", "0", "-5", "1/0", "1/-2", "0/1", "inf", "NaN", "1001", "2000/1",
+        ] {
+            assert!(parse_ratio(s).is_err(), "{:?} 应被拒绝", s);
+        }
+        assert!(parse_ratio("1000").is_ok());
+    }
+}
@@ -0,0 +1,501 @@
+//! 输入回传：viewer 把窗口中的键盘和鼠标事件发回服务端，由服务端注入合成器
+//!
+//! 协议是每行一个事件的文本，TCP 上按换行分隔，UDP 上每个数据包一个事件，可以用 nc 手动测试：
+//!
+//! ```text
+//! key <evdev 键码> <1|0>       键盘按下/松开
+//! motion <x> <y>              指针位置，接收端画面中的归一化坐标（0~1）
+//! button <evdev 按键码> <1|0>  鼠标按下/松开
+//! axis <dx> <dy>              滚轮，正值向右/向下
+//! ```
+//!
+//! 指针坐标相对于接收端看到的画面，服务端按当前的裁剪和缩放设置换算回合成器画面。
+//! 输入通道没有认证，只应该在可信网络中使用。
+
+use crate::address::{AddressError, HostPort};
+use crate::crop::CropRect;
+use crate::scale;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// 输入通道的传输协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum InputProtocol {
+    /// 可靠、有序，适合跨网络使用
+    #[default]
+    Tcp,
+    /// 延迟更低，丢包时会丢失事件
+    Udp,
+}
+
+impl fmt::Display for InputProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputProtocol::Tcp => write!(f, "tcp"),
+            InputProtocol::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+/// 服务端的输入通道设置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputOptions {
+    pub protocol: InputProtocol,
+    /// 监听地址
+    pub address: String,
+}
+
+/// 输入事件
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Key { key: u32, pressed: bool },
+    Motion { x: f64, y: f64 },
+    Button { button: u32, pressed: bool },
+    Axis { dx: f64, dy: f64 },
+}
+
+impl InputEvent {
+    /// 解析一行事件
+    pub fn parse(line: &str) -> Result<InputEvent, String> {
+        let mut parts = line.split_whitespace();
+        let kind = parts.next().ok_or("空的输入事件")?;
+        let args: Vec<&str> = parts.collect();
+        let invalid = || format!("无效的输入事件: {}", line.trim());
+        if args.len() != 2 {
+            return Err(invalid());
+        }
+        let code = |s: &str| s.parse::<u32>().map_err(|_| invalid());
+        let number = |s: &str| {
+            s.parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .ok_or_else(invalid)
+        };
+        let pressed = |s: &str| match s {
+            "1" => Ok(true),
+            "0" => Ok(false),
+            _ => Err(invalid()),
+        };
+        match kind {
+            "key" => Ok(InputEvent::Key {
+                key: code(args[0])?,
+                pressed: pressed(args[1])?,
+            }),
+            "motion" => Ok(InputEvent::Motion {
+                x: number(args[0])?,
+                y: number(args[1])?,
+            }),
+            "button" => Ok(InputEvent::Button {
+                button: code(args[0])?,
+                pressed: pressed(args[1])?,
+            }),
+            "axis" => Ok(InputEvent::Axis {
+                dx: number(args[0])?,
+                dy: number(args[1])?,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// 与 parse 对应的文本格式（不带换行）
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputEvent::Key { key, pressed } => write!(f, "key {} {}", key, *pressed as u8),
+            InputEvent::Motion { x, y } => write!(f, "motion {:.5} {:.5}", x, y),
+            InputEvent::Button { button, pressed } => {
+                write!(f, "button {} {}", button, *pressed as u8)
+            }
+            InputEvent::Axis { dx, dy } => write!(f, "axis {} {}", dx, dy),
+        }
+    }
+}
+
+/// 输入通道错误
+#[derive(Debug)]
+pub enum InputError {
+    /// 地址无效
+    Address {
+        address: String,
+        source: AddressError,
+    },
+    /// 无法监听或连接
+    Io {
+        address: SocketAddr,
+        source: std::io::Error,
+    },
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Address { address, source } => {
+                write!(f, "输入通道地址 {} 无效: {}", address, source)
+            }
+            InputError::Io { address, source } => {
+                write!(f, "无法使用输入通道地址 {}: {}", address, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InputError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InputError::Address { source, .. } => Some(source),
+            InputError::Io { source, .. } => Some(source),
+        }
+    }
+}
+
+fn resolve(address: &str) -> Result<SocketAddr, InputError> {
+    HostPort::parse(address)
+        .and_then(|a| a.resolve())
+        .map(|a| a.addr)
+        .map_err(|source| InputError::Address {
+            address: address.to_string(),
+            source,
+        })
+}
+
+/// 在后台线程中监听输入事件，收到的事件交给 deliver
+/// 地址在返回前绑定，端口被占用等错误会直接返回
+pub fn spawn_listener<F>(options: &InputOptions, deliver: F) -> Result<(), InputError>
+where
+    F: Fn(InputEvent) + Clone + Send + 'static,
+{
+    let address = resolve(&options.address)?;
+    let io_error = |source| InputError::Io { address, source };
+    if !address.ip().is_loopback() {
+        warn!(
+            "输入通道没有认证，任何能连接到 {} 的人都可以控制合成器",
+            address
+        );
+    }
+
+    match options.protocol {
+        InputProtocol::Tcp => {
+            let listener = TcpListener::bind(address).map_err(io_error)?;
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let deliver = deliver.clone();
+                            std::thread::spawn(move || read_tcp(stream, deliver));
+                        }
+                        Err(e) => warn!("接受输入连接失败: {}", e),
+                    }
+                }
+            });
+        }
+        InputProtocol::Udp => {
+            let socket = UdpSocket::bind(address).map_err(io_error)?;
+            std::thread::spawn(move || {
+                let mut buf = [0u8; 512];
+                loop {
+                    match socket.recv_from(&mut buf) {
+                        Ok((len, peer)) => {
+                            let text = String::from_utf8_lossy(&buf[..len]);
+                            deliver_line(&text, &peer, &deliver);
+                        }
+                        Err(e) => {
+                            warn!("接收输入事件失败: {}", e);
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    }
+    info!("输入通道: {}://{}", options.protocol, address);
+    Ok(())
+}
+
+fn read_tcp<F: Fn(InputEvent)>(stream: TcpStream, deliver: F) {
+    let peer = match stream.peer_addr() {
+        Ok(peer) => peer,
+        Err(_) => return,
+    };
+    // 事件很小，关闭 Nagle 算法以降低延迟
+    let _ = stream.set_nodelay(true);
+    info!("输入连接: {}", peer);
+    for line in BufReader::new(stream).lines() {
+        match line {
+            Ok(line) => deliver_line(&line, &peer, &deliver),
+            Err(e) => {
+                debug!("输入连接 {} 出错: {}", peer, e);
+                break;
+            }
+        }
+    }
+    info!("输入连接已断开: {}", peer);
+}
+
+fn deliver_line<F: Fn(InputEvent)>(text: &str, peer: &SocketAddr, deliver: &F) {
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        match InputEvent::parse(line) {
+            Ok(event) => deliver(event),
+            Err(e) => warn!("来自 {} 的{}", peer, e),
+        }
+    }
+}
+
+/// 把接收端画面中的归一化坐标换算为合成器画面中的像素坐标
+/// crop 和 scale 为当前输出的裁剪区域和输出尺寸，缩放时去掉 letterbox 的黑边
+pub fn map_pointer(
+    x: f64,
+    y: f64,
+    frame: (u32, u32),
+    crop: Option<&CropRect>,
+    scale: Option<(u32, u32)>,
+) -> (f64, f64) {
+    let region = crop.copied().unwrap_or(CropRect {
+        x: 0,
+        y: 0,
+        width: frame.0,
+        height: frame.1,
+    });
+    let (x, y) = match scale {
+        Some((width, height)) => {
+            let (x0, y0, w, h) = scale::letterbox((region.width, region.height), (width, height));
+            (
+                (x * width as f64 - x0 as f64) / w as f64,
+                (y * height as f64 - y0 as f64) / h as f64,
+            )
+        }
+        None => (x, y),
+    };
+    (
+        region.x as f64 + x.clamp(0.0, 1.0) * region.width as f64,
+        region.y as f64 + y.clamp(0.0, 1.0) * region.height as f64,
+    )
+}
+
+/// 接收端：在后台线程中把事件发送到服务端，调用方不会被网络阻塞
+/// TCP 连接断开后，在下一个事件到达时重新连接
+pub struct InputSender {
+    tx: mpsc::Sender<InputEvent>,
+}
+
+impl InputSender {
+    pub fn connect(protocol: InputProtocol, address: &str) -> Result<InputSender, InputError> {
+        let address = resolve(address)?;
+        let io_error = |source| InputError::Io { address, source };
+        let (tx, rx) = mpsc::channel::<InputEvent>();
+        match protocol {
+            InputProtocol::Tcp => {
+                let mut stream = Some(connect_tcp(address).map_err(io_error)?);
+                std::thread::spawn(move || {
+                    for event in rx {
+                        if stream.is_none() {
+                            stream = connect_tcp(address)
+                                .map_err(|e| warn!("无法连接输入通道 {}: {}", address, e))
+                                .ok();
+                        }
+                        if let Some(s) = &mut stream {
+                            if let Err(e) = writeln!(s, "{}", event) {
+                                warn!("发送输入事件失败: {}", e);
+                                stream = None;
+                            }
+                        }
+                    }
+                });
+            }
+            InputProtocol::Udp => {
+                let local: SocketAddr = if address.is_ipv4() {
+                    ([0, 0, 0, 0], 0).into()
+                } else {
+                    ([0u16; 8], 0).into()
+                };
+                let socket = UdpSocket::bind(local).map_err(io_error)?;
+                socket.connect(address).map_err(io_error)?;
+                std::thread::spawn(move || {
+                    for event in rx {
+                        if let Err(e) = socket.send(event.to_string().as_bytes()) {
+                            debug!("发送输入事件失败: {}", e);
+                        }
+                    }
+                });
+            }
+        }
+        info!("输入事件将发送到 {}://{}", protocol, address);
+        Ok(InputSender { tx })
+    }
+
+    pub fn send(&self, event: InputEvent) {
+        let _ = self.tx.send(event);
+    }
+}
+
+fn connect_tcp(address: SocketAddr) -> std::io::Result<TcpStream> {
+    let stream = TcpStream::connect(address)?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// X11 keysym 名称（GStreamer 导航事件中的按键名）对应的 evdev 键码
+pub fn evdev_key(name: &str) -> Option<u32> {
+    // 单个字母和数字不区分大小写（Shift 由单独的按键事件表示）
+    if let [c] = name.as_bytes() {
+        let c = c.to_ascii_lowercase();
+        const LETTERS: &[u8] = b"qwertyuiopasdfghjklzxcvbnm";
+        const LETTER_CODES: [u32; 26] = [
+            16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 30, 31, 32, 33, 34, 35, 36, 37, 38, 44, 45, 46,
+            47, 48, 49, 50,
+        ];
+        if let Some(i) = LETTERS.iter().position(|&l| l == c) {
+            return Some(LETTER_CODES[i]);
+        }
+        return match c {
+            b'1'..=b'9' => Some((c - b'1') as u32 + 2),
+            b'0' => Some(11),
+            _ => None,
+        };
+    }
+    if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<u32>().ok()) {
+        return match n {
+            1..=10 => Some(58 + n),
+            11 => Some(87),
+            12 => Some(88),
+            _ => None,
+        };
+    }
+    let code = match name {
+        "Escape" => 1,
+        "minus" => 12,
+        "equal" => 13,
+        "BackSpace" => 14,
+        "Tab" | "ISO_Left_Tab" => 15,
+        "bracketleft" => 26,
+        "bracketright" => 27,
+        "Return" => 28,
+        "Control_L" => 29,
+        "semicolon" => 39,
+        "apostrophe" => 40,
+        "grave" => 41,
+        "Shift_L" => 42,
+        "backslash" => 43,
+        "comma" => 51,
+        "period" => 52,
+        "slash" => 53,
+        "Shift_R" => 54,
+        "KP_Multiply" => 55,
+        "Alt_L" => 56,
+        "space" => 57,
+        "Caps_Lock" => 58,
+        "KP_Enter" => 96,
+        "Control_R" => 97,
+        "Alt_R" | "ISO_Level3_Shift" => 100,
+        "Home" => 102,
+        "Up" => 103,
+        "Page_Up" | "Prior" => 104,
+        "Left" => 105,
+        "Right" => 106,
+        "End" => 107,
+        "Down" => 108,
+        "Page_Down" | "Next" => 109,
+        "Insert" => 110,
+        "Delete" => 111,
+        "Super_L" | "Meta_L" => 125,
+        "Super_R" | "Meta_R" => 126,
+        "Menu" => 127,
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// GStreamer 导航事件中的鼠标按键编号（X11 约定）对应的 evdev 按键码
+/// 4~7 是滚轮，不在这里处理
+pub fn evdev_button(button: i32) -> Option<u32> {
+    match button {
+        1 => Some(0x110), // BTN_LEFT
+        2 => Some(0x112), // BTN_MIDDLE
+        3 => Some(0x111), // BTN_RIGHT
+        8 => Some(0x113), // BTN_SIDE
+        9 => Some(0x114), // BTN_EXTRA
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: (u32, u32) = (1920, 1080);
+
+    #[test]
+    fn maps_full_frame() {
+        assert_eq!(map_pointer(0.0, 0.0, FRAME, None, None), (0.0, 0.0));
+        assert_eq!(map_pointer(0.5, 0.5, FRAME, None, None), (960.0, 540.0));
+        assert_eq!(map_pointer(1.0, 1.0, FRAME, None, None), (1920.0, 1080.0));
+    }
+
+    #[test]
+    fn maps_into_crop_region() {
+        let crop = CropRect {
+            x: 320,
+            y: 180,
+            width: 1280,
+            height: 720,
+        };
+        assert_eq!(
+            map_pointer(0.0, 0.0, FRAME, Some(&crop), None),
+            (320.0, 180.0)
+        );
+        assert_eq!(
+            map_pointer(0.5, 0.5, FRAME, Some(&crop), None),
+            (960.0, 540.0)
+        );
+        assert_eq!(
+            map_pointer(1.0, 1.0, FRAME, Some(&crop), None),
+            (1600.0, 900.0)
+        );
+    }
+
+    #[test]
+    fn removes_letterbox_bars() {
+        // 16:9 画面缩放到 4:3，上下各有 60 像素的黑边
+        let scale = Some((640, 480));
+        assert_eq!(
+            map_pointer(0.5, 60.0 / 480.0, FRAME, None, scale),
+            (960.0, 0.0)
+        );
+        assert_eq!(
+            map_pointer(0.5, 420.0 / 480.0, FRAME, None, scale),
+            (960.0, 1080.0)
+        );
+        assert_eq!(map_pointer(0.25, 0.5, FRAME, None, scale), (480.0, 540.0));
+    }
+
+    #[test]
+    fn clamps_pointer_on_bars_and_outside_window() {
+        let scale = Some((640, 480));
+        assert_eq!(map_pointer(0.5, 0.0, FRAME, None, scale), (960.0, 0.0));
+        assert_eq!(map_pointer(0.5, 1.0, FRAME, None, scale), (960.0, 1080.0));
+        assert_eq!(map_pointer(-0.5, 1.5, FRAME, None, None), (0.0, 1080.0));
+    }
+
+    #[test]
+    fn combines_crop_and_scale() {
+        // 4:3 的裁剪区域缩放到 16:9，左右各有 160 像素的黑边
+        let crop = CropRect {
+            x: 100,
+            y: 100,
+            width: 640,
+            height: 480,
+        };
+        let scale = Some((1280, 720));
+        assert_eq!(
+            map_pointer(160.0 / 1280.0, 0.0, FRAME, Some(&crop), scale),
+            (100.0, 100.0)
+        );
+        assert_eq!(
+            map_pointer(0.5, 0.5, FRAME, Some(&crop), scale),
+            (420.0, 340.0)
+        );
+    }
+}
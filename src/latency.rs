@@ -0,0 +1,277 @@
+//! 端到端延迟测量
+//!
+//! 发送端在每一帧的左上角画一个像素条码，内容为取帧时的系统时间；接收端解码画面后读出条码，
+//! 与收到画面时的系统时间相减，得到包括编码、传输和解码在内的延迟。
+//!
+//! 条码由 8×9 个 16 像素的黑白方块组成，共 72 位：64 位时间戳（UNIX 时间，微秒）和 8 位校验。
+//! 方块与编码器的宏块对齐，经过有损编码后仍然可以按亮度区分。发送端和接收端需要使用同一个时钟，
+//! 例如在同一台机器上回环测试。
+
+use gst::glib;
+use gst_video::{VideoFormat, VideoFrameRef, VideoInfo};
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 方块边长（像素）
+const CELL: usize = 16;
+const COLUMNS: usize = 8;
+const ROWS: usize = 9;
+/// 条码占用的区域
+pub const WIDTH: u32 = (CELL * COLUMNS) as u32;
+pub const HEIGHT: u32 = (CELL * ROWS) as u32;
+/// 校验值的掩码，避免全黑画面被识别为有效条码
+const CHECK_MASK: u8 = 0xa5;
+
+/// 画面是否放得下条码
+pub fn fits(video_info: &VideoInfo) -> bool {
+    video_info.width() >= WIDTH && video_info.height() >= HEIGHT
+}
+
+/// 72 位数据，按行排列，每个字节高位在前
+fn encode(micros: u64) -> [bool; COLUMNS * ROWS] {
+    let bytes = micros.to_be_bytes();
+    let check = bytes.iter().fold(CHECK_MASK, |acc, b| acc ^ b);
+    let mut bits = [false; COLUMNS * ROWS];
+    for (i, byte) in bytes.iter().chain(std::iter::once(&check)).enumerate() {
+        for bit in 0..8 {
+            bits[i * 8 + bit] = byte & (0x80 >> bit) != 0;
+        }
+    }
+    bits
+}
+
+fn decode(bits: &[bool; COLUMNS * ROWS]) -> Option<u64> {
+    let mut bytes = [0u8; 9];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        for bit in 0..8 {
+            if bits[i * 8 + bit] {
+                *byte |= 0x80 >> bit;
+            }
+        }
+    }
+    let check = bytes[..8].iter().fold(CHECK_MASK, |acc, b| acc ^ b);
+    let micros = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+    (check == bytes[8] && micros != 0).then_some(micros)
+}
+
+/// 在 buffer 左上角画出 time 对应的条码
+/// 支持 RGBx、RGBA、BGRx、BGRA、RGB、NV12 和 I420
+pub fn stamp(
+    buffer: &mut gst::BufferRef,
+    video_info: &VideoInfo,
+    time: SystemTime,
+) -> Result<(), glib::BoolError> {
+    let micros = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default();
+    let bits = encode(micros);
+
+    let mut frame = VideoFrameRef::from_buffer_ref_writable(buffer, video_info)?;
+    let format = frame.format();
+    let strides: Vec<usize> = frame.plane_stride().iter().map(|&s| s as usize).collect();
+
+    // 每个像素写入的字节：packed RGB 的所有通道取同一个值，YUV 只写亮度平面
+    let (pixel_size, black, white) = match format {
+        VideoFormat::Rgbx | VideoFormat::Rgba | VideoFormat::Bgrx | VideoFormat::Bgra => {
+            (4, 0u8, 255u8)
+        }
+        VideoFormat::Rgb => (3, 0, 255),
+        VideoFormat::Nv12 | VideoFormat::I420 => (1, 16, 235),
+        _ => return Err(glib::bool_error!("延迟条码不支持格式 {:?}", format)),
+    };
+
+    let data = frame.plane_data_mut(0)?;
+    for (i, &bit) in bits.iter().enumerate() {
+        let (x0, y0) = ((i % COLUMNS) * CELL, (i / COLUMNS) * CELL);
+        let value = if bit { white } else { black };
+        for y in y0..y0 + CELL {
+            let row = &mut data[y * strides[0] + x0 * pixel_size..];
+            row[..CELL * pixel_size].fill(value);
+        }
+    }
+
+    // YUV 的色度平面填为中性值，条码区域保持灰度
+    let chroma = match format {
+        VideoFormat::Nv12 => vec![(1, WIDTH as usize)],
+        VideoFormat::I420 => vec![(1, WIDTH as usize / 2), (2, WIDTH as usize / 2)],
+        _ => Vec::new(),
+    };
+    for (plane, bytes) in chroma {
+        let data = frame.plane_data_mut(plane)?;
+        for y in 0..HEIGHT as usize / 2 {
+            data[y * strides[plane as usize]..][..bytes].fill(128);
+        }
+    }
+    Ok(())
+}
+
+/// 从亮度平面（GRAY8 或 YUV 的 Y 平面）读出条码中的时间
+/// 画面中没有有效条码时返回 None
+pub fn read(luma: &[u8], stride: usize, width: u32, height: u32) -> Option<SystemTime> {
+    if width < WIDTH || height < HEIGHT {
+        return None;
+    }
+    // 取每个方块中间一半区域的平均亮度，避开编码在方块边缘产生的振铃
+    let mut bits = [false; COLUMNS * ROWS];
+    for (i, bit) in bits.iter_mut().enumerate() {
+        let (x0, y0) = (
+            (i % COLUMNS) * CELL + CELL / 4,
+            (i / COLUMNS) * CELL + CELL / 4,
+        );
+        let mut sum = 0u32;
+        for y in y0..y0 + CELL / 2 {
+            let start = y * stride + x0;
+            sum += luma
+                .get(start..start + CELL / 2)?
+                .iter()
+                .map(|&v| v as u32)
+                .sum::<u32>();
+        }
+        *bit = sum / (CELL * CELL / 4) as u32 > 128;
+    }
+    decode(&bits).map(|micros| UNIX_EPOCH + Duration::from_micros(micros))
+}
+
+/// 延迟样本
+#[derive(Debug, Default)]
+pub struct LatencyStats {
+    samples: Vec<Duration>,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, latency: Duration) {
+        self.samples.push(latency);
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// 统计当前的样本，没有样本时返回 None
+    pub fn summary(&self) -> Option<LatencySummary> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        // 最近秩法：第 p 百分位是排序后第 ceil(p * n) 个样本
+        let percentile = |p: f64| {
+            let rank = (p * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        Some(LatencySummary {
+            count: sorted.len(),
+            min: sorted[0],
+            p50: percentile(0.50),
+            p90: percentile(0.90),
+            p99: percentile(0.99),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+/// 延迟统计结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub min: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{} 帧，min {:.1} ms，p50 {:.1} ms，p90 {:.1} ms，p99 {:.1} ms，max {:.1} ms",
+            self.count,
+            ms(self.min),
+            ms(self.p50),
+            ms(self.p90),
+            ms(self.p99),
+            ms(self.max)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按 stamp() 的布局把条码画到 GRAY8 画面上
+    fn paint(micros: u64, stride: usize, height: usize) -> Vec<u8> {
+        let mut luma = vec![0u8; stride * height];
+        for (i, &bit) in encode(micros).iter().enumerate() {
+            let (x0, y0) = ((i % COLUMNS) * CELL, (i / COLUMNS) * CELL);
+            for y in y0..y0 + CELL {
+                luma[y * stride + x0..][..CELL].fill(if bit { 235 } else { 16 });
+            }
+        }
+        luma
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        for micros in [1, 0x0123_4567_89ab_cdef, 1_700_000_000_000_000, u64::MAX] {
+            assert_eq!(decode(&encode(micros)), Some(micros));
+        }
+    }
+
+    #[test]
+    fn checksum_rejects_all_black_and_corrupted_codes() {
+        assert_eq!(decode(&[false; COLUMNS * ROWS]), None);
+        assert_eq!(decode(&[true; COLUMNS * ROWS]), None);
+        let mut bits = encode(1_700_000_000_000_000);
+        bits[5] = !bits[5];
+        assert_eq!(decode(&bits), None);
+    }
+
+    #[test]
+    fn reads_painted_code() {
+        let micros = 1_700_000_000_123_456;
+        let stride = WIDTH as usize + 32;
+        let luma = paint(micros, stride, HEIGHT as usize);
+        assert_eq!(
+            read(&luma, stride, WIDTH, HEIGHT),
+            Some(UNIX_EPOCH + Duration::from_micros(micros))
+        );
+    }
+
+    #[test]
+    fn read_rejects_black_and_small_frames() {
+        let stride = WIDTH as usize;
+        let black = vec![16u8; stride * HEIGHT as usize];
+        assert_eq!(read(&black, stride, WIDTH, HEIGHT), None);
+        let luma = paint(1_700_000_000_000_000, stride, HEIGHT as usize);
+        assert_eq!(read(&luma, stride, WIDTH - 1, HEIGHT), None);
+        // 数据比声明的尺寸短
+        assert_eq!(read(&luma[..stride * 8], stride, WIDTH, HEIGHT), None);
+    }
+
+    #[test]
+    fn summary_uses_nearest_rank_percentiles() {
+        let mut stats = LatencyStats::default();
+        assert_eq!(stats.summary(), None);
+        // 乱序记录 1..=100 ms
+        for ms in (1..=100).rev() {
+            stats.record(Duration::from_millis(ms));
+        }
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.min, Duration::from_millis(1));
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p90, Duration::from_millis(90));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+        assert_eq!(summary.max, Duration::from_millis(100));
+
+        stats.clear();
+        let only = Duration::from_millis(7);
+        stats.record(only);
+        let summary = stats.summary().unwrap();
+        assert_eq!((summary.min, summary.p50, summary.p99), (only, only, only));
+    }
+}
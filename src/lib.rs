@@ -0,0 +1,55 @@
+//! weadless：headless Wayland compositor，可以把画面通过 UDP/TCP/SRT 或 VNC 输出
+//!
+//! 命令行程序只是 [`Session`] 的一层包装，测试代码可以直接在进程内启动合成器：
+//!
+//! ```no_run
+//! use weadless::Session;
+//!
+//! let mut session = Session::builder()
+//!     .render_node("software")
+//!     .size(640, 480)
+//!     .build()
+//!     .expect("无法启动合成器");
+//!
+//! // 在合成器中启动一个 Wayland 客户端
+//! let mut client = session.command("weston-terminal").spawn().unwrap();
+//!
+//! // 注入输入并读取画面
+//! session.pointer_motion_absolute(100.0, 100.0);
+//! session.keyboard_input(30, true);
+//! session.keyboard_input(30, false);
+//! let frame = session.frame().unwrap();
+//! assert_eq!(frame.size(), 640 * 480 * 4);
+//!
+//! client.kill().unwrap();
+//! session.shutdown();
+//! ```
+
+pub mod adaptive;
+pub mod address;
+pub mod bus;
+mod change;
+pub mod clients;
+pub mod config;
+pub mod crop;
+pub mod encoder;
+pub mod error;
+pub mod format;
+pub mod framerate;
+pub mod input;
+pub mod latency;
+pub mod output;
+pub mod overlay;
+mod pacing;
+pub mod receiver;
+mod recorder;
+pub mod rtp;
+pub mod scale;
+pub mod session;
+pub mod source;
+pub mod stream;
+pub mod vnc;
+
+pub use error::{AppError, DisplayError};
+pub use session::{OutputConfig, ReloadHandle, Session, SessionBuilder, StopHandle};
+pub use stream::{DisplayThread, Frame, FrameStream, Lagged};
@@ -1,16 +1,37 @@
-use clap::Parser;
-use gst::prelude::*;
-use gst_app::AppSrc;
-use gst_video::VideoInfo;
-use std::sync::{Arc, Mutex, mpsc};
-use std::thread;
-use std::time::{Duration, Instant};
-use tracing::{error, info, warn, debug};
-use wayland_display_core::{GstVideoInfo, WaylandDisplay};
+use clap::{CommandFactory, FromArgMatches, Parser};
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{error, info, warn};
+use weadless::adaptive::AdaptiveOptions;
+use weadless::crop::CropRect;
+use weadless::format::PixelFormat;
+use weadless::input::{InputOptions, InputProtocol};
+use weadless::output::{AppSrcOptions, Protocol, SrtOptions, UdpOptions};
+use weadless::overlay::{OverlayOptions, OverlayPosition};
+use weadless::rtp::RtpOptions;
+use weadless::scale::{ScaleMethod, ScaleOptions};
+use weadless::source::{SourceKind, TestPattern};
+use weadless::vnc::VncOptions;
+use weadless::{address, config, AppError, OutputConfig, ReloadHandle, Session, SessionBuilder};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_override_self = true)]
 struct Args {
+    /// 配置文件（.toml、.yaml 或 .yml），命令行参数会覆盖文件中的设置
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// 使用配置文件中 [profiles.<名称>] 下的设置（需要 --config）
+    #[arg(long, requires = "config")]
+    profile: Option<String>,
+
+    /// 输出生效的配置并检查参数，然后退出；参数无效时以非零退出码退出
+    #[arg(long)]
+    check_config: bool,
+
     /// 渲染节点路径（例如 /dev/dri/renderD128），使用 "software" 进行软件渲染
     #[arg(long, default_value = "software")]
     render_node: String,
@@ -23,30 +44,162 @@ struct Args {
     #[arg(long, default_value_t = 1080)]
     height: u32,
 
-    /// 帧率（fps）
-    #[arg(long, default_value_t = 60)]
-    fps: u32,
+    /// 帧率（fps），支持分数和小数，例如 60000/1001、29.97、0.5；可变帧率模式下为最高帧率
+    #[arg(long, default_value = "60", value_parser = weadless::framerate::parse_frame_rate)]
+    fps: gst::Fraction,
+
+    /// 可变帧率：只在画面变化时发送新帧，输出流 caps 的 framerate 为 0/1（当 output=appsrc 时使用）
+    #[arg(long)]
+    variable_fps: bool,
+
+    /// 画面不变时跳过编码和发送（appsrc 和 vnc 输出都适用），只按保活间隔发送
+    #[arg(long)]
+    idle_skip: bool,
+
+    /// 按需取帧：没有客户端连接时暂停取帧和编码（适用于 vnc、tcp 以及 listener 模式的 srt）
+    #[arg(long)]
+    on_demand: bool,
+
+    /// 可变帧率或 --idle-skip 模式下画面不变时的保活间隔（毫秒）
+    #[arg(long, default_value_t = 1000)]
+    keepalive_interval: u64,
+
+    /// 可变帧率或 --idle-skip 模式下强制关键帧的间隔（秒）
+    #[arg(long, default_value_t = 2)]
+    keyframe_interval: u64,
+
+    /// 视频格式；合成器只输出 RGBx/RGBA/BGRx/BGRA，RGB、NV12 和 I420 需要在编码前多做一次 videoconvert 转换，不会更快
+    #[arg(long, value_enum, ignore_case = true, default_value = "RGBx")]
+    format: PixelFormat,
+
+    /// 帧来源；testpattern 不启动合成器，用 videotestsrc 生成的图案经过相同的编码和传输，用于排查网络和接收端
+    #[arg(long, value_enum, default_value = "compositor")]
+    source: SourceKind,
+
+    /// 测试图案（当 source=testpattern 时使用）
+    #[arg(long, value_enum, default_value = "smpte")]
+    test_pattern: TestPattern,
+
+    /// 输出方式
+    #[arg(long, value_enum, default_value = "none")]
+    output: OutputKind,
+
+    /// 只输出画面中的一个区域（WIDTHxHEIGHT+X+Y，例如 1280x720+0+0），在缩放之前应用；可以通过 SIGHUP 重新加载修改
+    #[arg(long, value_name = "WIDTHxHEIGHT+X+Y", value_parser = weadless::crop::parse_crop)]
+    crop: Option<CropRect>,
 
-    /// 视频格式（RGBx, RGBA, BGRx, BGRA）
-    #[arg(long, default_value = "RGBx")]
-    format: String,
+    /// 输出分辨率（WIDTHxHEIGHT，例如 1280x720），合成器分辨率不变；宽高比不同时添加黑边
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = weadless::scale::parse_size)]
+    scale: Option<(u32, u32)>,
 
-    /// 输出方式：none（默认，不输出）、appsrc（通过 appsrc 暴露到 UDP）、rtsp（RTSP 服务器）、vnc（VNC 服务器）
-    #[arg(long, default_value = "none")]
-    output: String,
+    /// 缩放算法（需要 --scale）
+    #[arg(long, value_enum, default_value = "bilinear")]
+    scale_method: ScaleMethod,
 
-    /// 输出地址（当 output=appsrc 时使用，格式：host:port）
+    /// 在画面上叠加墙上时间（当 output=appsrc 时使用，VNC 画面不叠加）
+    #[arg(long)]
+    overlay_clock: bool,
+
+    /// 在画面上叠加帧序号（当 output=appsrc 时使用）
+    #[arg(long)]
+    overlay_frame_number: bool,
+
+    /// 在画面上叠加会话名称（当 output=appsrc 时使用）
+    #[arg(long)]
+    overlay_session_name: Option<String>,
+
+    /// 在画面上叠加自定义文字（当 output=appsrc 时使用）
+    #[arg(long)]
+    overlay_text: Option<String>,
+
+    /// 叠加文字的位置
+    #[arg(long, value_enum, default_value = "top-left")]
+    overlay_position: OverlayPosition,
+
+    /// 在每帧左上角画出取帧时间的条码，配合 viewer --latency 测量端到端延迟（当 output=appsrc 时使用）
+    #[arg(long)]
+    latency_stamp: bool,
+
+    /// 输出地址（当 output=appsrc 时使用，格式：host:port、[ipv6]:port 或 ipv6%zone:port，主机名会通过 DNS 解析）
     #[arg(long, default_value = "127.0.0.1:5000")]
     output_address: String,
 
-    /// 传输协议（udp 或 tcp，当 output=appsrc 时使用）
-    #[arg(long, default_value = "udp")]
-    protocol: String,
+    /// 传输协议（当 output=appsrc 时使用）
+    #[arg(long, value_enum, default_value = "udp")]
+    protocol: Protocol,
+
+    /// 编码器码率（kbps，当 output=appsrc 时使用），不设置则使用编码器默认值
+    #[arg(long)]
+    bitrate: Option<u32>,
+
+    /// 根据 RTCP 接收报告（udp）或发送队列积压（tcp）自动调整编码器码率
+    #[arg(long)]
+    adaptive_bitrate: bool,
+
+    /// 自适应码率下限（kbps）
+    #[arg(long, default_value_t = 500)]
+    min_bitrate: u32,
+
+    /// 自适应码率上限（kbps）
+    #[arg(long, default_value_t = 8000)]
+    max_bitrate: u32,
+
+    /// 码率降到下限后仍然拥塞时降低帧率（需要 --adaptive-bitrate）
+    #[arg(long)]
+    adaptive_fps: bool,
+
+    /// 码率降到下限后仍然拥塞时降低输出分辨率（需要 --adaptive-bitrate）
+    #[arg(long)]
+    adaptive_scale: bool,
+
+    /// 封装格式（rtp 或 mpegts，当 protocol=udp/tcp 时使用；srt 总是使用 mpegts）
+    #[arg(long, default_value = "rtp")]
+    mux: String,
+
+    /// 额外的 UDP 单播目标（逗号分隔的 host:port 列表，与 --output-address 共用同一个编码器）
+    #[arg(long)]
+    udp_clients: Option<String>,
+
+    /// 组播 TTL（当 output-address 为组播地址时使用）
+    #[arg(long, default_value_t = 1)]
+    multicast_ttl: u8,
+
+    /// 发送组播使用的网络接口（例如 eth0，当 output-address 为组播地址时使用）
+    #[arg(long)]
+    multicast_iface: Option<String>,
+
+    /// 启用 RTP 重传（RTX），接收端通过 RTCP NACK 请求重传（当 protocol=udp、mux=rtp 时使用）
+    #[arg(long)]
+    rtx: bool,
+
+    /// ULPFEC 前向纠错冗余百分比，0 表示关闭（当 protocol=udp、mux=rtp 时使用）
+    #[arg(long, default_value_t = 0)]
+    fec_percentage: u32,
+
+    /// 使用 RED 封装 FEC 包（需要同时设置 --fec-percentage）
+    #[arg(long)]
+    fec_red: bool,
+
+    /// SRT 连接模式（listener 或 caller，当 protocol=srt 时使用）
+    #[arg(long, default_value = "listener")]
+    srt_mode: String,
+
+    /// SRT 延迟（毫秒，当 protocol=srt 时使用）
+    #[arg(long, default_value_t = 125)]
+    srt_latency: u32,
+
+    /// SRT 加密口令（10~79 个字符，当 protocol=srt 时使用，留空则不加密）
+    #[arg(long)]
+    srt_passphrase: Option<String>,
 
     /// RTSP 服务器端口（当 output=rtsp 时使用）
     #[arg(long, default_value_t = 8554)]
     rtsp_port: u16,
 
+    /// 监听地址（VNC 等监听服务使用，支持 IPv6，例如 :: 或 [::1]）
+    #[arg(long, default_value = "0.0.0.0")]
+    bind_address: String,
+
     /// VNC 服务器端口（当 output=vnc 时使用）
     #[arg(long, default_value_t = 5900)]
     vnc_port: u16,
@@ -54,6 +207,27 @@ struct Args {
     /// VNC 密码（当 output=vnc 时使用，留空则不设置密码）
     #[arg(long)]
     vnc_password: Option<String>,
+
+    /// 监听 viewer --input 发回的键盘和鼠标事件并注入合成器（例如 127.0.0.1:5001），没有认证
+    #[arg(long, value_name = "ADDRESS")]
+    input_listen: Option<String>,
+
+    /// 输入通道的传输协议
+    #[arg(long, value_enum, default_value = "tcp")]
+    input_protocol: InputProtocol,
+}
+
+/// 输出方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputKind {
+    /// 不输出，只运行合成器
+    None,
+    /// 编码为 H.264 后通过 UDP、TCP 或 SRT 发送
+    Appsrc,
+    /// RTSP 服务器（尚未实现）
+    Rtsp,
+    /// 内置 VNC 服务器
+    Vnc,
 }
 
 fn main() {
@@ -65,10 +239,11 @@ fn main() {
         )
         .init();
 
-    // 初始化 GStreamer
-    gst::init().expect("Failed to initialize GStreamer");
+    let (args, matches) = parse_args();
 
-    let args = Args::parse();
+    if args.check_config {
+        check_config(&args, &matches);
+    }
 
     info!("启动 headless Wayland compositor...");
     info!(
@@ -76,521 +251,270 @@ fn main() {
         args.width, args.height, args.fps, args.format
     );
 
-    // 创建 WaylandDisplay
-    let mut display = match WaylandDisplay::new(Some(args.render_node.clone())) {
-        Ok(display) => display,
-        Err(e) => {
-            eprintln!("无法创建 WaylandDisplay: {:?}", e);
-            eprintln!("提示: 如果使用软件渲染，请使用 --render-node software");
-            std::process::exit(1);
-        }
+    let builder = match session_builder(&args) {
+        Ok(builder) => builder,
+        Err(e) => exit_with(e),
     };
 
-    // 等待 compositor 线程初始化完成
-    std::thread::sleep(Duration::from_millis(100));
+    let mut session = match builder.build() {
+        Ok(session) => session,
+        Err(e) => exit_with(e),
+    };
 
-    // 获取环境变量（包括 Wayland socket）
-    let env_vars: Vec<String> = display.env_vars().map(|s| s.to_string()).collect();
-    for env_var in &env_vars {
+    // 输出环境变量（包括 Wayland socket）
+    for env_var in session.env_vars() {
         info!("环境变量: {}", env_var);
-        if env_var.starts_with("WAYLAND_DISPLAY=") {
-            let socket = env_var.strip_prefix("WAYLAND_DISPLAY=").unwrap();
-            println!("\n✓ Wayland compositor 已启动");
-            println!("  Socket: {}", socket);
-            println!("  使用以下命令连接:");
-            println!("    export WAYLAND_DISPLAY={}", socket);
-            println!("    # 然后启动你的 Wayland 应用，例如:");
-            println!("    # WAYLAND_DISPLAY={} weston-terminal", socket);
-            println!();
-        }
     }
-
-    // 创建视频信息
-    let video_format = match args.format.as_str() {
-        "RGBx" => gst_video::VideoFormat::Rgbx,
-        "RGBA" => gst_video::VideoFormat::Rgba,
-        "BGRx" => gst_video::VideoFormat::Bgrx,
-        "BGRA" => gst_video::VideoFormat::Bgra,
-        _ => {
-            warn!("未知格式 {}, 使用 RGBx", args.format);
-            gst_video::VideoFormat::Rgbx
-        }
-    };
-
-    let video_info = VideoInfo::builder(video_format, args.width, args.height)
-        .fps(gst::Fraction::new(args.fps as i32, 1))
-        .build()
-        .expect("Failed to build VideoInfo");
-
-    // 设置视频信息（这会创建输出）
-    display.set_video_info(GstVideoInfo::RAW(video_info.clone()));
-
-    // 根据输出选项创建相应的输出
-    let (stop_tx, stop_rx) = mpsc::channel();
-
-    // 区分不同的输出类型
-    enum OutputType {
-        AppSrc(AppSrc, mpsc::Sender<()>),
-        Vnc(Arc<Mutex<rustvncserver::VncServer>>, mpsc::Sender<()>),
+    if let Some(socket) = session.wayland_display() {
+        println!("\n✓ Wayland compositor 已启动");
+        println!("  Socket: {}", socket);
+        println!("  使用以下命令连接:");
+        println!("    export WAYLAND_DISPLAY={}", socket);
+        println!("    # 然后启动你的 Wayland 应用，例如:");
+        println!("    # WAYLAND_DISPLAY={} weston-terminal", socket);
+        println!();
     }
 
-    let output_opt = match args.output.as_str() {
-        "appsrc" => {
-            info!("使用 appsrc 方式暴露输出流到 {}: {}", args.protocol.to_uppercase(), args.output_address);
-            match start_appsrc_output(
-                video_info.clone(),
-                args.output_address.clone(),
-                args.protocol.as_str(),
-            ) {
-                Ok((appsrc, tx)) => Some(OutputType::AppSrc(appsrc, tx)),
-                Err(e) => {
-                    error!("无法启动输出流: {}", e);
-                    eprintln!("错误: {}", e);
-                    std::process::exit(1);
-                }
-            }
-        }
-        "rtsp" => {
-            info!("使用 RTSP 服务器暴露输出流，端口: {}", args.rtsp_port);
-            warn!("RTSP 服务器功能尚未实现，请使用 --output appsrc");
-            // TODO: 实现 RTSP 服务器
-            None
-        }
-        "vnc" => {
-            info!("使用 VNC 服务器暴露输出流，端口: {}", args.vnc_port);
-            match start_vnc_output(
-                video_info.clone(),
-                args.vnc_port,
-                args.vnc_password.clone(),
-            ) {
-                Ok((vnc_server, tx)) => Some(OutputType::Vnc(vnc_server, tx)),
-                Err(e) => {
-                    error!("无法启动 VNC 服务器: {}", e);
-                    eprintln!("错误: {}", e);
-                    std::process::exit(1);
-                }
-            }
-        }
-        _ => {
-            info!("未启用输出流暴露（使用 --output appsrc、--output rtsp 或 --output vnc 启用）");
-            None
-        }
-    };
-
-    info!("Wayland compositor 运行中...");
+    if args.source == SourceKind::TestPattern {
+        info!("正在输出测试图案 {}...", args.test_pattern);
+    } else {
+        info!("Wayland compositor 运行中...");
+    }
     info!("按 Ctrl+C 退出");
 
     // 设置 Ctrl+C 处理器
-    let output_opt_clone = output_opt.clone();
+    let stop = session.stop_handle();
     ctrlc::set_handler(move || {
         info!("收到退出信号，正在关闭...");
-        let _ = stop_tx.send(());
-        // 如果启用了输出流，也通知帧推送线程停止
-        if let Some(OutputType::AppSrc(_, ref tx)) = output_opt_clone {
-            let _ = tx.send(());
-        } else if let Some(OutputType::Vnc(_, ref tx)) = output_opt_clone {
-            let _ = tx.send(());
-        }
+        stop.stop();
     })
     .expect("无法设置 Ctrl+C 处理器");
 
+    // SIGHUP：重新读取配置文件
+    spawn_reload_handler(
+        args.config.clone(),
+        args.profile.clone(),
+        session.reload_handle(),
+    );
+
     // 主循环：如果启用了输出流，在主循环中获取帧并推送
-    match output_opt {
-        Some(OutputType::AppSrc(ref appsrc, _)) => {
-        let target_frame_duration = Duration::from_secs_f64(1.0 / video_info.fps().numer() as f64);
-        let mut frame_count = 0u64;
-        let start_time = Instant::now();
-
-        loop {
-            // 检查是否收到停止信号（带超时，避免阻塞）
-            match stop_rx.recv_timeout(Duration::from_millis(10)) {
-                Ok(_) => break,
-                Err(mpsc::RecvTimeoutError::Timeout) => {
-                    // 超时是正常的，继续处理帧
-                }
-                Err(mpsc::RecvTimeoutError::Disconnected) => break,
-            }
+    // 运行中出错时先清理资源，再按错误类别退出
+    let result = session.run();
+    session.shutdown();
+    if let Err(e) = result {
+        exit_with(e);
+    }
+}
 
-            // 获取帧并推送
-            match display.frame() {
-                Ok(buffer) => {
-                    let result = appsrc.push_buffer(buffer);
-                    if let Err(e) = result {
-                        error!("推送 buffer 失败: {:?}", e);
-                    } else {
-                        frame_count += 1;
-                        if frame_count % 60 == 0 {
-                            let elapsed = start_time.elapsed();
-                            let fps = frame_count as f64 / elapsed.as_secs_f64();
-                            debug!("已推送 {} 帧，平均帧率: {:.2} fps", frame_count, fps);
-                        }
-                    }
-                }
-                Err(e) => {
-                    let err_str = format!("{:?}", e);
-                    if err_str.contains("Flushing") || err_str.contains("Eos") {
-                        info!("Pipeline 正在关闭: {:?}", e);
-                        break;
-                    }
-                    warn!("获取帧失败: {:?}，继续尝试...", e);
-                }
-            }
+/// 开关参数也接受 --flag=false，用来关闭配置文件中打开的开关
+fn command() -> clap::Command {
+    config::switch_values(Args::command())
+}
 
-            // 控制帧率
-            thread::sleep(target_frame_duration);
-        }
+/// 解析命令行参数；指定了 --config 时，配置文件中的设置放在命令行参数之前，
+/// 这样命令行上的同名参数会覆盖文件中的值
+fn parse_args() -> (Args, clap::ArgMatches) {
+    let mut command = command();
+    let matches = command.get_matches_mut();
+
+    if let Some(path) = matches.get_one::<PathBuf>("config") {
+        let profile = matches.get_one::<String>("profile");
+        return match args_with_config(path, profile.map(String::as_str)) {
+            Ok(parsed) => parsed,
+            Err(e) => exit_with(e),
+        };
+    }
 
-            // 发送 EOS
-            let _ = appsrc.end_of_stream();
-        }
-        Some(OutputType::Vnc(ref vnc_server, _)) => {
-            let target_frame_duration = Duration::from_secs_f64(1.0 / video_info.fps().numer() as f64);
-            let mut frame_count = 0u64;
-            let start_time = Instant::now();
-
-            loop {
-                // 检查是否收到停止信号（带超时，避免阻塞）
-                match stop_rx.recv_timeout(Duration::from_millis(10)) {
-                    Ok(_) => break,
-                    Err(mpsc::RecvTimeoutError::Timeout) => {
-                        // 超时是正常的，继续处理帧
-                    }
-                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
-                }
-
-                // 获取帧并发送到 VNC 服务器
-                match display.frame() {
-                    Ok(buffer) => {
-                        if let Err(e) = send_frame_to_vnc(vnc_server, &buffer, &video_info) {
-                            error!("发送帧到 VNC 服务器失败: {:?}", e);
-                        } else {
-                            frame_count += 1;
-                            if frame_count % 60 == 0 {
-                                let elapsed = start_time.elapsed();
-                                let fps = frame_count as f64 / elapsed.as_secs_f64();
-                                debug!("已发送 {} 帧到 VNC，平均帧率: {:.2} fps", frame_count, fps);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        let err_str = format!("{:?}", e);
-                        if err_str.contains("Flushing") || err_str.contains("Eos") {
-                            info!("正在关闭: {:?}", e);
-                            break;
-                        }
-                        warn!("获取帧失败: {:?}，继续尝试...", e);
-                    }
-                }
-
-                // 控制帧率
-                thread::sleep(target_frame_duration);
-            }
-        }
-        None => {
-            // 未启用输出流，直接等待退出信号
-            stop_rx.recv().unwrap();
-        }
+    match Args::from_arg_matches(&matches) {
+        Ok(args) => (args, matches),
+        Err(e) => e.format(&mut command).exit(),
     }
+}
 
-    info!("正在清理资源...");
-    // display 会在 drop 时自动清理
+/// 读取配置文件，与进程的命令行参数合并后重新解析
+fn args_with_config(
+    path: &Path,
+    profile: Option<&str>,
+) -> Result<(Args, clap::ArgMatches), AppError> {
+    let mut command = command();
+    let from_file = config::config_args(path, profile, &command)
+        .map_err(|e| AppError::Config(e.to_string()))?;
+
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+    let cli = argv.split_off(1.min(argv.len()));
+    argv.extend(from_file);
+    argv.extend(cli);
+
+    // 配置文件中的值无法解析时，clap 会指出对应的参数名
+    let clap_error =
+        |e: clap::Error| AppError::Config(e.render().to_string().trim_end().to_string());
+    let matches = command.try_get_matches_from_mut(argv).map_err(clap_error)?;
+    let args = Args::from_arg_matches(&matches).map_err(|e| clap_error(e.format(&mut command)))?;
+    Ok((args, matches))
 }
 
-/// 使用 appsrc 方式暴露输出流
-/// 返回 appsrc 和停止信号的发送端
-/// 注意：frame() 必须在创建 WaylandDisplay 的线程中调用
-fn start_appsrc_output(
-    video_info: VideoInfo,
-    output_address: String,
-    protocol: &str,
-) -> Result<(AppSrc, mpsc::Sender<()>), String> {
-    // 创建 GStreamer pipeline
-    let pipeline = gst::Pipeline::new();
-
-    // 创建 appsrc 元素
-    let appsrc = AppSrc::builder()
-        .name("source")
-        .caps(&gst::Caps::builder("video/x-raw")
-            .field("format", video_info.format().to_string())
-            .field("width", video_info.width() as i32)
-            .field("height", video_info.height() as i32)
-            .field("framerate", video_info.fps())
-            .build())
-        .format(gst::Format::Time)
-        .is_live(true)
-        .build();
-
-    // 创建 videoconvert、编码器、rtph264pay 和 udpsink
-    let videoconvert = gst::ElementFactory::make("videoconvert")
-        .build()
-        .map_err(|e| format!("无法创建 videoconvert: {:?}", e))?;
-    
-    // 尝试使用不同的 H.264 编码器（按优先级顺序）
-    let encoder = if gst::ElementFactory::find("vaapih264enc").is_some() {
-        info!("使用 vaapih264enc（硬件加速）");
-        gst::ElementFactory::make("vaapih264enc")
-            .property("tune", "low-power")
-            .build()
-            .map_err(|e| format!("无法创建 vaapih264enc: {:?}", e))?
-    } else if gst::ElementFactory::find("nvh264enc").is_some() {
-        info!("使用 nvh264enc（NVIDIA 硬件加速）");
-        // nvh264enc 的属性需要枚举类型，这里使用默认配置
-        // 默认配置已经针对低延迟进行了优化
-        gst::ElementFactory::make("nvh264enc")
-            .build()
-            .map_err(|e| format!("无法创建 nvh264enc: {:?}", e))?
-    } else if gst::ElementFactory::find("x264enc").is_some() {
-        info!("使用 x264enc（软件编码）");
-        gst::ElementFactory::make("x264enc")
-            .property("tune", "zerolatency")
-            .property("speed-preset", "ultrafast")
-            .build()
-            .map_err(|e| format!("无法创建 x264enc: {:?}", e))?
-    } else if gst::ElementFactory::find("avenc_h264").is_some() {
-        info!("使用 avenc_h264（软件编码）");
-        gst::ElementFactory::make("avenc_h264")
-            .property("preset", "ultrafast")
-            .build()
-            .map_err(|e| format!("无法创建 avenc_h264: {:?}", e))?
-    } else {
-        return Err(format!(
-            "未找到可用的 H.264 编码器。请安装以下插件之一：\n\
-            - gstreamer1.0-plugins-good (x264enc)\n\
-            - gstreamer1.0-plugins-bad (avenc_h264)\n\
-            - gstreamer1.0-plugins-bad (vaapih264enc, 需要硬件支持)\n\
-            - gstreamer1.0-plugins-bad (nvh264enc, 需要 NVIDIA GPU)"
-        ));
-    };
-    
-    let rtph264pay = gst::ElementFactory::make("rtph264pay")
-        .property("config-interval", 1i32)
-        .property("pt", 96u32)
-        .build()
-        .map_err(|e| format!("无法创建 rtph264pay: {:?}", e))?;
-
-    // 解析输出地址
-    let (host, port) = output_address
-        .split_once(':')
-        .ok_or_else(|| "输出地址格式错误，应为 host:port".to_string())?;
-    let port: u16 = port.parse()
-        .map_err(|e| format!("端口必须是数字: {}", e))?;
-
-    // 根据协议选择 sink
-    let sink = match protocol.to_lowercase().as_str() {
-        "udp" => {
-            gst::ElementFactory::make("udpsink")
-                .property("host", host)
-                .property("port", port as i32)
-                .build()
-                .map_err(|e| format!("无法创建 udpsink: {:?}", e))?
-        }
-        "tcp" => {
-            // tcpserversink 需要 sync=false 以避免阻塞
-            // 默认配置会在连接断开后继续等待新连接
-            gst::ElementFactory::make("tcpserversink")
-                .property("host", host)
-                .property("port", port as i32)
-                .property("sync", false)
-                .build()
-                .map_err(|e| format!("无法创建 tcpserversink: {:?}", e))?
-        }
-        _ => {
-            return Err(format!("不支持的协议: {}，支持 udp 或 tcp", protocol));
-        }
+/// 根据参数创建 SessionBuilder
+fn session_builder(args: &Args) -> Result<SessionBuilder, AppError> {
+    let builder = Session::builder()
+        .render_node(args.render_node.clone())
+        .size(args.width, args.height)
+        .fps(args.fps)
+        .format(args.format.video_format())
+        .source(args.source)
+        .test_pattern(args.test_pattern)
+        .variable_fps(args.variable_fps)
+        .idle_skip(args.idle_skip)
+        .on_demand(args.on_demand)
+        .keepalive_interval(Duration::from_millis(args.keepalive_interval))
+        .keyframe_interval(Duration::from_secs(args.keyframe_interval));
+    let builder = match &args.input_listen {
+        Some(address) => builder.input(InputOptions {
+            protocol: args.input_protocol,
+            address: address.clone(),
+        }),
+        None => builder,
     };
+    Ok(match output_config(args)? {
+        Some(output) => builder.output(output),
+        None => builder,
+    })
+}
 
-    // 添加元素到 pipeline
-    pipeline
-        .add_many(&[
-            appsrc.upcast_ref(),
-            &videoconvert,
-            &encoder,
-            &rtph264pay,
-            &sink,
-        ])
-        .map_err(|e| format!("无法添加元素到 pipeline: {:?}", e))?;
-
-    // 链接元素
-    gst::Element::link_many(&[
-        appsrc.upcast_ref(),
-        &videoconvert,
-        &encoder,
-        &rtph264pay,
-        &sink,
-    ])
-    .map_err(|e| format!("无法链接元素: {:?}", e))?;
-
-    // 启动 pipeline
-    pipeline
-        .set_state(gst::State::Playing)
-        .map_err(|e| format!("无法启动 pipeline: {:?}", e))?;
-
-    info!("GStreamer pipeline 已启动");
-    info!("输出流地址: {}://{}:{}", protocol, host, port);
-    info!("客户端可以使用以下命令接收:");
-    match protocol.to_lowercase().as_str() {
-        "udp" => {
-            info!(
-                "  gst-launch-1.0 udpsrc port={} caps=\"application/x-rtp,media=video,encoding-name=H264,payload=96\" ! rtph264depay ! avdec_h264 ! videoconvert ! autovideosink",
-                port
-            );
+/// 收到 SIGHUP 时重新读取配置文件（命令行参数仍然优先），把新配置交给 Session
+/// 配置无效时保持原有设置
+fn spawn_reload_handler(config: Option<PathBuf>, profile: Option<String>, reload: ReloadHandle) {
+    let mut signals = match Signals::new([SIGHUP]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("无法设置 SIGHUP 处理器，不支持重新加载配置: {}", e);
+            return;
         }
-        "tcp" => {
-            info!(
-                "  gst-launch-1.0 tcpclientsrc host={} port={} ! application/x-rtp,encoding-name=H264,payload=96 ! rtph264depay ! h264parse ! avdec_h264 ! videoconvert ! autovideosink",
-                host, port
-            );
+    };
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            let Some(path) = &config else {
+                warn!("收到 SIGHUP，但没有指定 --config，忽略");
+                continue;
+            };
+            info!("收到 SIGHUP，重新读取配置文件 {}", path.display());
+            let parsed = args_with_config(path, profile.as_deref())
+                .and_then(|(args, _)| session_builder(&args));
+            match parsed {
+                Ok(builder) => reload.reload(builder),
+                Err(e) => error!("配置无效，保持原有设置: {}", e),
+            }
         }
-        _ => {}
-    }
-
-    // 返回 appsrc 和停止信号发送端（暂时不使用，因为我们在主循环中处理）
-    let (frame_stop_tx, _frame_stop_rx) = mpsc::channel();
-    Ok((appsrc, frame_stop_tx))
+    });
 }
 
-/// 使用 VNC 服务器方式暴露输出流
-/// 返回 VNC 服务器和停止信号的发送端
-/// 注意：frame() 必须在创建 WaylandDisplay 的线程中调用
-fn start_vnc_output(
-    video_info: VideoInfo,
-    vnc_port: u16,
-    vnc_password: Option<String>,
-) -> Result<(Arc<Mutex<rustvncserver::VncServer>>, mpsc::Sender<()>), String> {
-    use rustvncserver::VncServer;
-
-    let width = video_info.width() as u16;
-    let height = video_info.height() as u16;
-    let name = "weadless".to_string();
-    let password = vnc_password.clone();
-
-    // 创建 VNC 服务器（异步 API，需要在 tokio runtime 中运行）
-    let (vnc_server, mut event_rx) = VncServer::new(width, height, name, password);
-
-    // 在单独的线程中运行 VNC 服务器
-    let server_clone = Arc::new(Mutex::new(vnc_server));
-    let server_for_listen = server_clone.clone();
-    let port = vnc_port;
-
-    thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new()
-            .map_err(|e| format!("无法创建 tokio runtime: {:?}", e))
-            .unwrap();
-
-        rt.block_on(async {
-            // 处理服务器事件
-            let event_handle = tokio::spawn(async move {
-                while let Some(event) = event_rx.recv().await {
-                    match event {
-                        rustvncserver::ServerEvent::ClientConnected { id, address } => {
-                            info!("VNC 客户端 {} ({}) 已连接", id, address);
-                        }
-                        rustvncserver::ServerEvent::ClientDisconnected { id } => {
-                            info!("VNC 客户端 {} 已断开", id);
-                        }
-                        _ => {}
-                    }
-                }
-            });
-
-            // 启动服务器
-            let server = server_for_listen.lock().unwrap();
-            if let Err(e) = server.listen(port).await {
-                error!("VNC 服务器监听失败: {:?}", e);
-            }
+/// --check-config：输出生效的配置并检查参数取值，不启动合成器
+fn check_config(args: &Args, matches: &clap::ArgMatches) -> ! {
+    config::print_effective(
+        &command(),
+        matches,
+        &["config", "profile", "check-config"],
+    );
 
-            event_handle.await.ok();
-        });
+    // 裁剪区域需要位于合成器画面内
+    let crop = args.crop.map_or(Ok(()), |crop| {
+        let even = matches!(args.output, OutputKind::Appsrc);
+        crop.validate(args.width, args.height, even)
+            .map_err(AppError::Config)
     });
-
-    info!("VNC 服务器已启动");
-    info!("VNC 服务器地址: 0.0.0.0:{}", vnc_port);
-    info!("使用 VNC 客户端连接:");
-    info!("  vncviewer localhost:{}", vnc_port);
-    info!("  或者: vncviewer localhost::{}", vnc_port);
-    if vnc_password.is_some() {
-        info!("  需要密码认证");
+    let result = crop
+        .and_then(|()| output_config(args))
+        .and_then(|output| match output {
+            Some(OutputConfig::AppSrc(options)) => options.validate().map_err(AppError::from),
+            Some(OutputConfig::Vnc(options)) => options.validate().map_err(AppError::from),
+            None => Ok(()),
+        });
+    match result {
+        Ok(()) => {
+            eprintln!("配置有效");
+            std::process::exit(0);
+        }
+        Err(e) => exit_with(e),
     }
-
-    // 返回 VNC 服务器和停止信号发送端
-    let (frame_stop_tx, _frame_stop_rx) = mpsc::channel();
-    Ok((server_clone, frame_stop_tx))
 }
 
-/// 将 GStreamer buffer 发送到 VNC 服务器
-fn send_frame_to_vnc(
-    vnc_server: &Arc<Mutex<rustvncserver::VncServer>>,
-    buffer: &gst::Buffer,
-    video_info: &VideoInfo,
-) -> Result<(), String> {
-    // 获取 buffer 的数据
-    let map = buffer
-        .map_readable()
-        .map_err(|e| format!("无法映射 buffer: {:?}", e))?;
-
-    let data = map.as_slice();
-    let width = video_info.width() as usize;
-    let height = video_info.height() as usize;
-
-    // 根据视频格式处理数据
-    // VNC 需要 RGB888 格式（每个像素 3 字节）
-    let rgb_data = match video_info.format() {
-        gst_video::VideoFormat::Rgbx => {
-            // RGBx -> RGB (去掉 alpha 通道)
-            let mut rgb = Vec::with_capacity(width * height * 3);
-            for chunk in data.chunks_exact(4) {
-                rgb.push(chunk[0]); // R
-                rgb.push(chunk[1]); // G
-                rgb.push(chunk[2]); // B
-            }
-            rgb
-        }
-        gst_video::VideoFormat::Rgba => {
-            // RGBA -> RGB (去掉 alpha 通道)
-            let mut rgb = Vec::with_capacity(width * height * 3);
-            for chunk in data.chunks_exact(4) {
-                rgb.push(chunk[0]); // R
-                rgb.push(chunk[1]); // G
-                rgb.push(chunk[2]); // B
-            }
-            rgb
+/// 根据命令行参数选择输出方式
+fn output_config(args: &Args) -> Result<Option<OutputConfig>, AppError> {
+    let scale = args.scale.map(|(width, height)| ScaleOptions {
+        width,
+        height,
+        method: args.scale_method,
+    });
+    match args.output {
+        OutputKind::Appsrc => Ok(Some(OutputConfig::AppSrc(AppSrcOptions {
+            variable_framerate: args.variable_fps,
+            format: Some(args.format.video_format()),
+            output_address: args.output_address.clone(),
+            protocol: args.protocol,
+            mux: args.mux.clone(),
+            bitrate: args.bitrate,
+            adaptive: args.adaptive_bitrate.then(|| AdaptiveOptions {
+                min_bitrate: args.min_bitrate,
+                max_bitrate: args.max_bitrate,
+                adapt_fps: args.adaptive_fps,
+                adapt_scale: args.adaptive_scale,
+            }),
+            crop: args.crop,
+            scale,
+            overlay: OverlayOptions {
+                clock: args.overlay_clock,
+                frame_number: args.overlay_frame_number,
+                session_name: args.overlay_session_name.clone(),
+                text: args.overlay_text.clone(),
+                position: args.overlay_position,
+            },
+            latency_stamp: args.latency_stamp,
+            udp: UdpOptions {
+                clients: args.udp_clients.clone(),
+                multicast_ttl: args.multicast_ttl,
+                multicast_iface: args.multicast_iface.clone(),
+            },
+            rtp: RtpOptions {
+                rtx: args.rtx,
+                fec_percentage: args.fec_percentage,
+                fec_red: args.fec_red,
+            },
+            srt: SrtOptions {
+                mode: args.srt_mode.clone(),
+                latency_ms: args.srt_latency,
+                passphrase: args.srt_passphrase.clone(),
+            },
+        }))),
+        OutputKind::Rtsp => {
+            info!("使用 RTSP 服务器暴露输出流，端口: {}", args.rtsp_port);
+            warn!("RTSP 服务器功能尚未实现，请使用 --output appsrc");
+            // TODO: 实现 RTSP 服务器
+            Ok(None)
         }
-        gst_video::VideoFormat::Bgrx => {
-            // BGRx -> RGB (交换 R 和 B，去掉 alpha)
-            let mut rgb = Vec::with_capacity(width * height * 3);
-            for chunk in data.chunks_exact(4) {
-                rgb.push(chunk[2]); // R
-                rgb.push(chunk[1]); // G
-                rgb.push(chunk[0]); // B
-            }
-            rgb
+        OutputKind::Vnc => {
+            let bind_address = address::parse_bind_address(&args.bind_address).map_err(|e| {
+                AppError::Config(format!("监听地址 {} 无效: {}", args.bind_address, e))
+            })?;
+            Ok(Some(OutputConfig::Vnc(VncOptions {
+                bind_address,
+                port: args.vnc_port,
+                password: args.vnc_password.clone(),
+                crop: args.crop,
+                scale,
+            })))
         }
-        gst_video::VideoFormat::Bgra => {
-            // BGRA -> RGB (交换 R 和 B，去掉 alpha)
-            let mut rgb = Vec::with_capacity(width * height * 3);
-            for chunk in data.chunks_exact(4) {
-                rgb.push(chunk[2]); // R
-                rgb.push(chunk[1]); // G
-                rgb.push(chunk[0]); // B
-            }
-            rgb
-        }
-        _ => {
-            return Err(format!(
-                "不支持的视频格式: {:?}，VNC 需要 RGB 格式",
-                video_info.format()
-            ));
+        OutputKind::None => {
+            info!("未启用输出流暴露（使用 --output appsrc、--output rtsp 或 --output vnc 启用）");
+            Ok(None)
         }
-    };
-
-    // 发送帧到 VNC 服务器
-    let mut server = vnc_server
-        .lock()
-        .map_err(|e| format!("无法锁定 VNC 服务器: {:?}", e))?;
-
-    server
-        .update_framebuffer(0, 0, width as u16, height as u16, &rgb_data)
-        .map_err(|e| format!("无法更新 VNC 帧缓冲区: {:?}", e))?;
+    }
+}
 
-    Ok(())
+/// 输出错误和处理建议，并以错误类别对应的退出码退出
+fn exit_with(e: AppError) -> ! {
+    error!("{}", e);
+    eprintln!("错误: {}", e);
+    if let Some(hint) = e.hint() {
+        eprintln!("提示: {}", hint);
+    }
+    std::process::exit(e.exit_code());
 }
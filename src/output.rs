@@ -0,0 +1,1112 @@
+//! appsrc 输出：把合成器的画面编码为 H.264，通过 UDP、TCP 或 SRT 发送
+//!
+//! pipeline 出现致命错误时会被拆除，并按指数退避重建；错误来自编码器时，
+//! 重建会换用优先级列表中的下一个编码器（例如硬件编码器异常时退回软件编码）。
+
+use crate::adaptive::{self, AdaptiveOptions, Feedback};
+use crate::address::{AddressError, HostPort, ResolvedAddress};
+use crate::bus::{self, Failure};
+use crate::clients::ClientTracker;
+use crate::crop::{self, CropRect};
+use crate::encoder::{self, EncoderError};
+use crate::latency;
+use crate::overlay::{self, OverlayOptions, OverlayPosition};
+use crate::rtp::{self, RtpOptions};
+use crate::scale::{self, ScaleOptions};
+use gst::glib;
+use gst::prelude::*;
+use gst_app::AppSrc;
+use gst_video::{VideoFormat, VideoInfo};
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// 第一次重建前的等待时间，之后每次失败翻倍
+const RESTART_DELAY_MIN: Duration = Duration::from_secs(1);
+/// 重建等待时间的上限
+const RESTART_DELAY_MAX: Duration = Duration::from_secs(30);
+/// pipeline 稳定运行超过该时长后清零失败次数
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+/// 退出时等待 EOS 传递到 sink 的最长时间
+const EOS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 输出错误
+#[derive(Debug)]
+pub enum OutputError {
+    /// 无法创建 GStreamer 元素，通常是缺少插件
+    Element {
+        name: &'static str,
+        source: glib::BoolError,
+    },
+    /// 无法把元素加入 pipeline 或链接元素
+    Link {
+        what: &'static str,
+        source: glib::BoolError,
+    },
+    /// 没有可用的编码器
+    Encoder(EncoderError),
+    /// 输出地址无效
+    Address {
+        address: String,
+        source: AddressError,
+    },
+    /// 参数无效或参数组合不受支持
+    InvalidOption(String),
+    /// pipeline 运行时出错（包括无法进入 Playing 状态）
+    Pipeline(Failure),
+}
+
+impl OutputError {
+    pub fn element(name: &'static str, source: glib::BoolError) -> OutputError {
+        OutputError::Element { name, source }
+    }
+
+    pub fn link(what: &'static str, source: glib::BoolError) -> OutputError {
+        OutputError::Link { what, source }
+    }
+
+    /// 错误是否来自编码器，这种情况下可以换用下一个编码器重试
+    fn from_encoder(&self) -> bool {
+        matches!(self, OutputError::Pipeline(failure) if failure.from_encoder)
+    }
+
+    /// 给用户的处理建议
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            OutputError::Element {
+                name: "srtsink", ..
+            } => Some("srtsink 位于 gstreamer1.0-plugins-bad，需要 libsrt 支持".to_string()),
+            OutputError::Element { name, .. } => Some(format!(
+                "使用 gst-inspect-1.0 {} 检查对应的 GStreamer 插件是否已安装",
+                name
+            )),
+            OutputError::Encoder(e) => e.hint(),
+            OutputError::Address { .. } => Some(
+                "地址格式为 host:port，例如 127.0.0.1:5000、[::1]:5000 或 [fe80::1%eth0]:5000"
+                    .to_string(),
+            ),
+            OutputError::Pipeline(_) => Some(
+                "端口可能已被占用，或者编码器无法初始化；设置 GST_DEBUG=3 查看详细信息".to_string(),
+            ),
+            OutputError::Link { .. } | OutputError::InvalidOption(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputError::Element { name, source } => write!(f, "无法创建 {}: {}", name, source),
+            OutputError::Link { what, source } => write!(f, "无法{}: {}", what, source),
+            OutputError::Encoder(e) => write!(f, "{}", e),
+            OutputError::Address { address, source } => {
+                write!(f, "输出地址 {} 无效: {}", address, source)
+            }
+            OutputError::InvalidOption(message) => write!(f, "{}", message),
+            OutputError::Pipeline(failure) => write!(f, "{}", failure),
+        }
+    }
+}
+
+impl std::error::Error for OutputError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OutputError::Element { source, .. } | OutputError::Link { source, .. } => Some(source),
+            OutputError::Encoder(e) => Some(e),
+            OutputError::Address { source, .. } => Some(source),
+            OutputError::InvalidOption(_) | OutputError::Pipeline(_) => None,
+        }
+    }
+}
+
+impl From<EncoderError> for OutputError {
+    fn from(e: EncoderError) -> OutputError {
+        OutputError::Encoder(e)
+    }
+}
+
+/// 正在运行的 appsrc pipeline
+struct AppSrcPipeline {
+    pipeline: gst::Pipeline,
+    appsrc: AppSrc,
+    encoder: gst::Element,
+    /// 设置了裁剪区域时的 videocrop，用于在运行中修改裁剪区域
+    videocrop: Option<gst::Element>,
+    /// 编码器在优先级列表中的位置
+    encoder_index: usize,
+}
+
+impl Drop for AppSrcPipeline {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+/// appsrc 输出，负责监控 pipeline 并在出错后重建
+/// 注意：frame() 必须在创建 WaylandDisplay 的线程中调用，所以 poll() 由主循环驱动，不使用单独的线程
+pub struct AppSrcOutput {
+    video_info: VideoInfo,
+    options: AppSrcOptions,
+    clients: ClientTracker,
+    running: Option<AppSrcPipeline>,
+    /// 重建时从优先级列表的这个位置开始选择编码器
+    encoder_start: usize,
+    /// 连续失败次数，决定重建前的等待时间
+    failures: u32,
+    /// 下一次尝试重建的时间，pipeline 正在运行时为 None
+    restart_at: Option<Instant>,
+    started_at: Instant,
+}
+
+impl AppSrcOutput {
+    /// 创建并启动 pipeline
+    /// 编码器在启动时出错会直接尝试下一个编码器，其他错误返回给调用方
+    pub fn start(
+        video_info: VideoInfo,
+        options: AppSrcOptions,
+        clients: ClientTracker,
+    ) -> Result<AppSrcOutput, OutputError> {
+        options.validate()?;
+        validate_crop(&options, &video_info)?;
+
+        let mut output = AppSrcOutput {
+            video_info,
+            options,
+            clients,
+            running: None,
+            encoder_start: 0,
+            failures: 0,
+            restart_at: None,
+            started_at: Instant::now(),
+        };
+        loop {
+            match output.launch() {
+                Ok(()) => return Ok(output),
+                Err(e) if e.from_encoder() && output.next_encoder() => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn options(&self) -> &AppSrcOptions {
+        &self.options
+    }
+
+    /// 当前的 appsrc，pipeline 正在重建时为 None
+    pub fn appsrc(&self) -> Option<&AppSrc> {
+        self.running.as_ref().map(|running| &running.appsrc)
+    }
+
+    /// 处理总线消息，出错时拆除 pipeline，到时间后重建
+    /// 返回 true 表示刚刚启动了新的 pipeline
+    pub fn poll(&mut self) -> bool {
+        if let Some(running) = &self.running {
+            match bus::drain(&running.pipeline, &running.encoder) {
+                Some(failure) => self.fail(OutputError::Pipeline(failure)),
+                None => {
+                    if self.failures > 0 && self.started_at.elapsed() >= STABLE_AFTER {
+                        self.failures = 0;
+                    }
+                }
+            }
+            return false;
+        }
+
+        if self.restart_at.is_some_and(|at| Instant::now() < at) {
+            return false;
+        }
+        info!(
+            attempt = self.failures,
+            encoder = encoder::ENCODERS[self.encoder_start],
+            "正在重建 pipeline"
+        );
+        match self.launch() {
+            Ok(()) => {
+                info!("pipeline 已恢复");
+                true
+            }
+            Err(e) => {
+                self.fail(e);
+                false
+            }
+        }
+    }
+
+    /// 换用新的选项：码率、裁剪区域和延迟条码直接在运行中的 pipeline 上修改，其他变化需要重建 pipeline
+    /// 合成器和 Wayland 客户端不受影响，接收端可能需要重新连接
+    pub fn reconfigure(&mut self, options: AppSrcOptions) -> Result<(), OutputError> {
+        options.validate()?;
+        validate_crop(&options, &self.video_info)?;
+        if options == self.options {
+            return Ok(());
+        }
+
+        if !self.apply_live(&options) {
+            info!("输出选项已修改，正在重建 pipeline");
+            self.running = None;
+            self.failures = 0;
+            self.options = options;
+            if let Err(e) = self.launch() {
+                self.fail(e);
+            }
+            return Ok(());
+        }
+        self.options = options;
+        Ok(())
+    }
+
+    /// 在运行中的 pipeline 上应用新选项，需要重建时返回 false
+    fn apply_live(&self, options: &AppSrcOptions) -> bool {
+        // pipeline 正在等待重建时，新选项会在重建时生效
+        let Some(running) = &self.running else {
+            return true;
+        };
+        let others_unchanged = AppSrcOptions {
+            bitrate: self.options.bitrate,
+            crop: self.options.crop,
+            // 条码由取帧循环画出，与 pipeline 无关
+            latency_stamp: self.options.latency_stamp,
+            ..options.clone()
+        } == self.options;
+        if !others_unchanged {
+            return false;
+        }
+
+        // 添加或去掉 videocrop 需要重建
+        if options.crop != self.options.crop {
+            match (&options.crop, &running.videocrop) {
+                (Some(crop), Some(videocrop)) => {
+                    crop::set_videocrop(
+                        videocrop,
+                        crop,
+                        self.video_info.width(),
+                        self.video_info.height(),
+                    );
+                    info!("裁剪区域已修改为 {}", crop);
+                }
+                _ => return false,
+            }
+        }
+
+        if options.bitrate != self.options.bitrate {
+            match (options.bitrate, &options.adaptive) {
+                // 启用自适应码率时由控制器调整码率，新值在下次重建时作为初始码率
+                (Some(_), Some(_)) => {
+                    info!("自适应码率已启用，新的码率将在 pipeline 重建时作为初始码率")
+                }
+                (Some(kbps), None) => {
+                    adaptive::set_encoder_bitrate(&running.encoder, kbps);
+                    info!("编码器码率已修改为 {} kbps", kbps);
+                }
+                // 恢复编码器的默认码率需要重建
+                (None, _) => return false,
+            }
+        }
+        true
+    }
+
+    /// 发送 EOS 并等待它到达 sink，然后停止 pipeline
+    pub fn shutdown(mut self) {
+        let Some(running) = self.running.take() else {
+            return;
+        };
+        let _ = running.appsrc.end_of_stream();
+        if let Some(bus) = running.pipeline.bus() {
+            let eos = bus.timed_pop_filtered(
+                gst::ClockTime::from_nseconds(EOS_TIMEOUT.as_nanos() as u64),
+                &[gst::MessageType::Eos, gst::MessageType::Error],
+            );
+            if eos.is_none() {
+                debug!("等待 EOS 超时");
+            }
+        }
+    }
+
+    /// 构建 pipeline 并切换到 Playing
+    fn launch(&mut self) -> Result<(), OutputError> {
+        let built = build_pipeline(
+            &self.video_info,
+            &self.options,
+            &self.clients,
+            self.encoder_start,
+        )?;
+
+        if let Err(e) = built.pipeline.set_state(gst::State::Playing) {
+            // 状态切换失败的原因会以错误消息的形式出现在总线上
+            let failure = bus::drain(&built.pipeline, &built.encoder).unwrap_or_else(|| Failure {
+                source: String::new(),
+                message: e.to_string(),
+                from_encoder: false,
+            });
+            return Err(OutputError::Pipeline(Failure {
+                message: format!("无法启动 pipeline: {}", failure.message),
+                ..failure
+            }));
+        }
+
+        info!("GStreamer pipeline 已启动");
+        self.encoder_start = built.encoder_index;
+        self.running = Some(built);
+        self.restart_at = None;
+        self.started_at = Instant::now();
+        Ok(())
+    }
+
+    /// 拆除 pipeline 并安排重建
+    fn fail(&mut self, error: OutputError) {
+        self.running = None;
+        if error.from_encoder() && !self.next_encoder() {
+            warn!(
+                "没有其他可用的编码器，继续使用 {}",
+                encoder::ENCODERS[self.encoder_start]
+            );
+        }
+
+        self.failures += 1;
+        let delay = RESTART_DELAY_MIN
+            .saturating_mul(1 << (self.failures - 1).min(16))
+            .min(RESTART_DELAY_MAX);
+        self.restart_at = Some(Instant::now() + delay);
+        error!(
+            error = %error,
+            failures = self.failures,
+            retry_in_s = delay.as_secs(),
+            "pipeline 已停止，稍后重建"
+        );
+    }
+
+    /// 换用优先级列表中的下一个编码器，没有更多编码器时返回 false
+    fn next_encoder(&mut self) -> bool {
+        let next = self.encoder_start + 1;
+        if !encoder::ENCODERS[next.min(encoder::ENCODERS.len())..]
+            .iter()
+            .any(|name| gst::ElementFactory::find(name).is_some())
+        {
+            return false;
+        }
+        warn!(
+            failed = encoder::ENCODERS[self.encoder_start],
+            "编码器出错，改用优先级列表中的下一个编码器"
+        );
+        self.encoder_start = next;
+        true
+    }
+}
+
+/// 检查裁剪区域是否在合成器画面内，以及画面能否放下延迟条码
+fn validate_crop(options: &AppSrcOptions, video_info: &VideoInfo) -> Result<(), OutputError> {
+    if options.latency_stamp && !latency::fits(video_info) {
+        return Err(OutputError::InvalidOption(format!(
+            "画面太小，放不下 {}x{} 的延迟条码",
+            latency::WIDTH,
+            latency::HEIGHT
+        )));
+    }
+    match &options.crop {
+        Some(crop) => crop
+            .validate(video_info.width(), video_info.height(), true)
+            .map_err(OutputError::InvalidOption),
+        None => Ok(()),
+    }
+}
+
+/// 尺寸不同、格式和帧率相同的 VideoInfo
+fn resized_info(video_info: &VideoInfo, width: u32, height: u32) -> Result<VideoInfo, OutputError> {
+    VideoInfo::builder(video_info.format(), width, height)
+        .fps(video_info.fps())
+        .build()
+        .map_err(|e| {
+            OutputError::InvalidOption(format!("输出尺寸 {}x{} 无效: {}", width, height, e))
+        })
+}
+
+/// 创建 appsrc pipeline（尚未启动）
+/// encoder_start 为编码器优先级列表中开始尝试的位置
+fn build_pipeline(
+    video_info: &VideoInfo,
+    options: &AppSrcOptions,
+    clients: &ClientTracker,
+    encoder_start: usize,
+) -> Result<AppSrcPipeline, OutputError> {
+    let udp_options = &options.udp;
+    let rtp_options = &options.rtp;
+    let srt_options = &options.srt;
+
+    // 可变帧率的流 caps 中 framerate 为 0/1，max-framerate 为取帧的最高帧率
+    let stream_info = if options.variable_framerate {
+        if options
+            .adaptive
+            .as_ref()
+            .is_some_and(|adaptive| adaptive.adapt_fps)
+        {
+            return Err(OutputError::InvalidOption(
+                "--adaptive-fps 不能与 --variable-fps 一起使用".to_string(),
+            ));
+        }
+        VideoInfo::builder(video_info.format(), video_info.width(), video_info.height())
+            .fps(gst::Fraction::new(0, 1))
+            .build()
+            .map_err(|e| OutputError::InvalidOption(format!("无法创建可变帧率 VideoInfo: {}", e)))?
+    } else {
+        video_info.clone()
+    };
+    let mut caps_builder = gst::Caps::builder("video/x-raw")
+        .field("format", stream_info.format().to_string())
+        .field("width", stream_info.width() as i32)
+        .field("height", stream_info.height() as i32)
+        .field("framerate", stream_info.fps());
+    if options.variable_framerate {
+        caps_builder = caps_builder.field("max-framerate", video_info.fps());
+    }
+    let caps = caps_builder.build();
+
+    // 创建 GStreamer pipeline
+    let pipeline = gst::Pipeline::new();
+
+    // 创建 appsrc 元素
+    let appsrc = AppSrc::builder()
+        .name("source")
+        .caps(&caps)
+        .format(gst::Format::Time)
+        .is_live(true)
+        .build();
+
+    // 按优先级选择 H.264 编码器，见 encoder 模块
+    let (encoder_index, encoder) = encoder::make_encoder(encoder_start)?;
+
+    // 指定了与输入不同的格式（例如合成器输出 RGBx、编码器需要 NV12）时转换为该格式；
+    // 否则编码器能直接接受输入的格式时不需要 videoconvert
+    let target = options
+        .format
+        .filter(|format| *format != stream_info.format());
+    let videoconvert = if target.is_none() && encoder::accepts(&encoder, &caps) {
+        debug!(format = %stream_info.format(), "编码器直接接受输入格式，跳过 videoconvert");
+        Vec::new()
+    } else {
+        let mut elements = vec![gst::ElementFactory::make("videoconvert")
+            .build()
+            .map_err(|e| OutputError::element("videoconvert", e))?];
+        if let Some(format) = target {
+            debug!(from = %stream_info.format(), to = %format, "在编码前转换格式");
+            let caps = gst::Caps::builder("video/x-raw")
+                .field("format", format.to_string())
+                .build();
+            elements.push(
+                gst::ElementFactory::make("capsfilter")
+                    .property("caps", &caps)
+                    .build()
+                    .map_err(|e| OutputError::element("capsfilter", e))?,
+            );
+        }
+        elements
+    };
+
+    // 固定码率或自适应码率的初始值
+    let initial_bitrate = match &options.adaptive {
+        Some(adaptive) => Some(
+            options
+                .bitrate
+                .unwrap_or(adaptive.max_bitrate)
+                .clamp(adaptive.min_bitrate, adaptive.max_bitrate),
+        ),
+        None => options.bitrate,
+    };
+    if let Some(kbps) = initial_bitrate {
+        adaptive::configure_encoder_bitrate(&encoder, kbps);
+    }
+
+    // 只输出部分画面时，在 appsrc 之后插入 videocrop
+    let (videocrop, cropped_info) = match &options.crop {
+        Some(crop) => (
+            Some(
+                crop::make_videocrop(crop, stream_info.width(), stream_info.height())
+                    .map_err(|e| OutputError::element("videocrop", e))?,
+            ),
+            resized_info(&stream_info, crop.width, crop.height)?,
+        ),
+        None => (None, stream_info.clone()),
+    };
+
+    // 以不同于合成器的分辨率输出时，在编码器前插入 videoscale ! capsfilter
+    let (resize, encoded_info) = match &options.scale {
+        Some(scale) => (
+            scale::make_scale_elements(scale)?,
+            resized_info(&cropped_info, scale.width, scale.height)?,
+        ),
+        None => (Vec::new(), cropped_info),
+    };
+
+    // 叠加文字放在裁剪和缩放之后，字号不受缩放影响
+    let overlay = overlay::make_overlay(&options.overlay)?;
+
+    // 自适应帧率/分辨率需要在编码器前插入 videorate ! videoscale ! capsfilter
+    let scaler = match &options.adaptive {
+        Some(adaptive) if adaptive.needs_scaler() => adaptive::make_scaler_elements(&encoded_info)?,
+        _ => Vec::new(),
+    };
+
+    // 解析输出地址（支持 IPv6、zone ID 和主机名）
+    let address = HostPort::parse(&options.output_address)
+        .and_then(|address| address.resolve())
+        .map_err(|source| OutputError::Address {
+            address: options.output_address.clone(),
+            source,
+        })?;
+    let host = address.host();
+    let port = address.port();
+
+    // 根据协议选择封装方式和 sink
+    // udp/tcp 默认发送 RTP，也可以选择 MPEG-TS；srt 总是使用 MPEG-TS 封装
+    let protocol = options.protocol;
+    let mux = match protocol {
+        Protocol::Srt => "mpegts".to_string(),
+        _ => options.mux.to_lowercase(),
+    };
+    let mut tail = match mux.as_str() {
+        "rtp" => {
+            let rtph264pay = gst::ElementFactory::make("rtph264pay")
+                .property("config-interval", 1i32)
+                .property("pt", 96u32)
+                .build()
+                .map_err(|e| OutputError::element("rtph264pay", e))?;
+            let mut elements = vec![rtph264pay];
+            // TCP 是字节流，按 RFC 4571 给每个 RTP 包加上长度前缀，接收端才能分出包边界
+            if protocol == Protocol::Tcp {
+                let rtpstreampay = gst::ElementFactory::make("rtpstreampay")
+                    .build()
+                    .map_err(|e| OutputError::element("rtpstreampay", e))?;
+                elements.push(rtpstreampay);
+            }
+            elements
+        }
+        "mpegts" => make_mpegts_mux_elements()?,
+        _ => {
+            return Err(OutputError::InvalidOption(format!(
+                "不支持的封装格式: {}，支持 rtp 或 mpegts",
+                mux
+            )));
+        }
+    };
+
+    let extra = match protocol {
+        Protocol::Udp => resolve_udp_clients(udp_options)?,
+        _ => Vec::new(),
+    };
+    let sink = match protocol {
+        Protocol::Udp => make_udp_sink(&address, &extra, udp_options)?,
+        Protocol::Tcp => {
+            // tcpserversink 需要 sync=false 以避免阻塞
+            // 默认配置会在连接断开后继续等待新连接
+            let sink = gst::ElementFactory::make("tcpserversink")
+                .property("host", host.as_str())
+                .property("port", port as i32)
+                .property("sync", false)
+                .build()
+                .map_err(|e| OutputError::element("tcpserversink", e))?;
+            track_sink_clients(&sink, "client-added", "client-removed", clients);
+            sink
+        }
+        Protocol::Srt => {
+            let sink = make_srt_sink(&address, srt_options)?;
+            if srt_options.mode == "listener" {
+                track_sink_clients(&sink, "caller-added", "caller-removed", clients);
+            }
+            sink
+        }
+    };
+    // UDP 上的 RTP 经过 rtpbin 发送，以便支持 RTCP、RTX 和 FEC
+    let use_rtpbin = protocol == Protocol::Udp && mux == "rtp";
+    if !use_rtpbin {
+        tail.push(sink.clone());
+    }
+
+    let mut elements: Vec<&gst::Element> = vec![appsrc.upcast_ref()];
+    elements.extend(videocrop.iter());
+    elements.extend(videoconvert.iter());
+    elements.extend(resize.iter());
+    elements.extend(overlay.iter());
+    elements.extend(scaler.iter());
+    elements.push(&encoder);
+    elements.extend(tail.iter());
+
+    // 添加元素到 pipeline
+    pipeline
+        .add_many(&elements)
+        .map_err(|e| OutputError::link("添加元素到 pipeline", e))?;
+
+    // 链接元素
+    gst::Element::link_many(&elements).map_err(|e| OutputError::link("链接元素", e))?;
+
+    let rtpbin = if use_rtpbin {
+        pipeline
+            .add(&sink)
+            .map_err(|e| OutputError::link("添加元素到 pipeline", e))?;
+        let payloader = tail.last().expect("RTP 封装元素不存在");
+        Some(rtp::link_udp_session(
+            &pipeline,
+            payloader,
+            &sink,
+            &address,
+            &extra,
+            rtp_options,
+        )?)
+    } else {
+        None
+    };
+
+    info!("输出流地址: {}://{}（{}）", protocol, address, mux);
+    if let Some(clients) = &udp_options.clients {
+        info!("额外的 UDP 单播目标: {}", clients);
+    }
+    info!("客户端可以使用以下命令接收:");
+    match (protocol, mux.as_str()) {
+        (Protocol::Udp, "rtp") => {
+            rtp::log_receiver_hint(&address, rtp_options);
+            // viewer 只按 H.264 的负载类型接收，无法处理 RED 封装、FEC 包和 RTX 重传
+            if rtp_options.rtx || rtp_options.fec_percentage > 0 {
+                info!("  viewer 不支持 RTX 和 FEC，请使用上面的 gst-launch-1.0 命令接收");
+            } else {
+                info!("  或者: viewer --udp {}", address);
+            }
+            if let Some(rtpbin) = &rtpbin {
+                rtp::spawn_rtcp_stats_logger(rtpbin, Duration::from_secs(5));
+            }
+        }
+        (Protocol::Udp, _) => {
+            info!(
+                "  gst-launch-1.0 udpsrc {}port={} ! tsdemux ! h264parse ! avdec_h264 ! videoconvert ! autovideosink",
+                multicast_address_param(&address), port
+            );
+            if address.ip().is_multicast() {
+                info!("  或者: ffplay udp://{}", address);
+            } else {
+                info!("  或者: ffplay udp://@:{}", port);
+            }
+            info!("  或者: viewer --udp {} --mux mpegts", address);
+        }
+        (Protocol::Tcp, "rtp") => {
+            info!(
+                "  gst-launch-1.0 tcpclientsrc host={} port={} ! application/x-rtp-stream,media=video,clock-rate=90000,encoding-name=H264,payload=96 ! rtpstreamdepay ! rtph264depay ! h264parse ! avdec_h264 ! videoconvert ! autovideosink",
+                host, port
+            );
+            info!("  或者: viewer --tcp {}", address);
+        }
+        (Protocol::Tcp, _) => {
+            info!(
+                "  gst-launch-1.0 tcpclientsrc host={} port={} ! tsdemux ! h264parse ! avdec_h264 ! videoconvert ! autovideosink",
+                host, port
+            );
+            info!("  或者: ffplay tcp://{}", address);
+            info!("  或者: viewer --tcp {} --mux mpegts", address);
+        }
+        (Protocol::Srt, _) => {
+            // 接收端使用与服务端相反的连接模式
+            let uri = if srt_options.mode == "caller" {
+                format!("srt://:{}?mode=listener", port)
+            } else {
+                format!("srt://{}?mode=caller", address)
+            };
+            let passphrase = if srt_options.passphrase.is_some() {
+                " passphrase=<口令>"
+            } else {
+                ""
+            };
+            info!(
+                "  gst-launch-1.0 srtsrc uri=\"{}\" latency={}{} ! tsdemux ! h264parse ! avdec_h264 ! videoconvert ! autovideosink",
+                uri, srt_options.latency_ms, passphrase
+            );
+            spawn_srt_stats_logger(&sink, Duration::from_secs(5));
+        }
+    }
+
+    if let (Some(adaptive), Some(kbps)) = (&options.adaptive, initial_bitrate) {
+        let feedback = match (protocol, &rtpbin) {
+            (_, Some(rtpbin)) => Some(Feedback::Rtcp(rtpbin.clone())),
+            (Protocol::Tcp, None) => Some(Feedback::TcpQueue(sink.clone())),
+            _ => None,
+        };
+        match feedback {
+            Some(feedback) => adaptive::spawn_controller(
+                adaptive.clone(),
+                &encoder,
+                scaler.last(),
+                feedback,
+                encoded_info.clone(),
+                kbps,
+            ),
+            None => warn!(
+                "自适应码率只支持 UDP（RTP）和 TCP 输出，当前为 {}（{}），使用固定码率 {} kbps",
+                protocol, mux, kbps
+            ),
+        }
+    }
+
+    Ok(AppSrcPipeline {
+        pipeline,
+        appsrc,
+        encoder,
+        videocrop,
+        encoder_index,
+    })
+}
+
+/// 通过 sink 的连接/断开信号更新客户端计数
+fn track_sink_clients(sink: &gst::Element, added: &str, removed: &str, clients: &ClientTracker) {
+    let added_clients = clients.clone();
+    sink.connect(added, false, move |_| {
+        added_clients.connected();
+        None
+    });
+    let removed_clients = clients.clone();
+    sink.connect(removed, false, move |_| {
+        removed_clients.disconnected();
+        None
+    });
+}
+
+/// 传输协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Protocol {
+    Udp,
+    Tcp,
+    Srt,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Protocol::Udp => "udp",
+            Protocol::Tcp => "tcp",
+            Protocol::Srt => "srt",
+        })
+    }
+}
+
+/// appsrc 输出选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppSrcOptions {
+    /// 可变帧率（caps 中 framerate 为 0/1），由 Session 按 variable_fps 设置
+    pub variable_framerate: bool,
+    /// 交给编码器的格式，None 表示与输入的帧相同；由 Session 按 format 设置
+    pub format: Option<VideoFormat>,
+    /// 输出地址（host:port）
+    pub output_address: String,
+    /// 传输协议
+    pub protocol: Protocol,
+    /// 封装格式：rtp 或 mpegts
+    pub mux: String,
+    /// 编码器码率（kbps）
+    pub bitrate: Option<u32>,
+    /// 自适应码率，None 表示使用固定码率
+    pub adaptive: Option<AdaptiveOptions>,
+    /// 裁剪区域，None 表示输出整个画面
+    pub crop: Option<CropRect>,
+    /// 输出分辨率，None 表示与合成器（或裁剪区域）相同
+    pub scale: Option<ScaleOptions>,
+    /// 叠加文字，默认不叠加
+    pub overlay: OverlayOptions,
+    /// 在每帧左上角画出取帧时间的条码，用于测量端到端延迟，见 latency 模块
+    pub latency_stamp: bool,
+    pub udp: UdpOptions,
+    pub rtp: RtpOptions,
+    pub srt: SrtOptions,
+}
+
+impl Default for AppSrcOptions {
+    fn default() -> AppSrcOptions {
+        AppSrcOptions {
+            variable_framerate: false,
+            format: None,
+            output_address: "127.0.0.1:5000".to_string(),
+            protocol: Protocol::Udp,
+            mux: "rtp".to_string(),
+            bitrate: None,
+            adaptive: None,
+            crop: None,
+            scale: None,
+            overlay: OverlayOptions::default(),
+            latency_stamp: false,
+            udp: UdpOptions::default(),
+            rtp: RtpOptions::default(),
+            srt: SrtOptions::default(),
+        }
+    }
+}
+
+impl AppSrcOptions {
+    /// 检查参数取值和参数组合，不创建任何元素，也不解析主机名
+    pub fn validate(&self) -> Result<(), OutputError> {
+        let invalid = |message: String| Err(OutputError::InvalidOption(message));
+
+        if !matches!(self.mux.to_lowercase().as_str(), "rtp" | "mpegts") {
+            return invalid(format!(
+                "不支持的封装格式: {}，支持 rtp 或 mpegts",
+                self.mux
+            ));
+        }
+
+        HostPort::parse(&self.output_address).map_err(|source| OutputError::Address {
+            address: self.output_address.clone(),
+            source,
+        })?;
+        if let Some(extra) = &self.udp.clients {
+            for client in extra.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+                HostPort::parse(client).map_err(|source| OutputError::Address {
+                    address: client.to_string(),
+                    source,
+                })?;
+            }
+        }
+
+        if let Some(scale) = &self.scale {
+            scale.validate(true).map_err(OutputError::InvalidOption)?;
+        }
+        // 条码画在合成器画面上，裁剪和缩放会改变它的位置和大小
+        let rescaled = self.crop.is_some()
+            || self.scale.is_some()
+            || self
+                .adaptive
+                .as_ref()
+                .is_some_and(|adaptive| adaptive.adapt_scale);
+        if self.latency_stamp && rescaled {
+            return invalid(
+                "--latency-stamp 不能与 --crop、--scale 或 --adaptive-scale 一起使用".to_string(),
+            );
+        }
+        let covered = !self.overlay.is_empty() && self.overlay.position == OverlayPosition::TopLeft;
+        if self.latency_stamp && covered {
+            return invalid("--latency-stamp 占用左上角，叠加文字请换一个位置".to_string());
+        }
+
+        if let Some(adaptive) = &self.adaptive {
+            if adaptive.min_bitrate == 0 || adaptive.min_bitrate > adaptive.max_bitrate {
+                return invalid(format!(
+                    "自适应码率范围无效: {}~{} kbps",
+                    adaptive.min_bitrate, adaptive.max_bitrate
+                ));
+            }
+            if adaptive.adapt_fps && self.variable_framerate {
+                return invalid("--adaptive-fps 不能与 --variable-fps 一起使用".to_string());
+            }
+        }
+
+        if self.rtp.fec_red && self.rtp.fec_percentage == 0 {
+            return invalid("--fec-red 需要同时设置 --fec-percentage".to_string());
+        }
+
+        if self.protocol == Protocol::Srt {
+            if !matches!(self.srt.mode.as_str(), "listener" | "caller") {
+                return invalid(format!(
+                    "不支持的 SRT 模式: {}，支持 listener 或 caller",
+                    self.srt.mode
+                ));
+            }
+            if let Some(passphrase) = &self.srt.passphrase {
+                if !(10..=79).contains(&passphrase.len()) {
+                    return invalid("SRT 口令长度必须在 10~79 个字符之间".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 是否能得知接收端的连接状态（用于按需取帧）
+    pub fn tracks_clients(&self) -> bool {
+        match self.protocol {
+            Protocol::Tcp => true,
+            Protocol::Srt => self.srt.mode == "listener",
+            Protocol::Udp => false,
+        }
+    }
+}
+
+/// UDP 输出选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UdpOptions {
+    /// 额外的单播目标（逗号分隔的 host:port 列表）
+    pub clients: Option<String>,
+    /// 组播 TTL
+    pub multicast_ttl: u8,
+    /// 发送组播使用的网络接口
+    pub multicast_iface: Option<String>,
+}
+
+/// SRT 输出选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrtOptions {
+    /// 连接模式：listener（等待接收端连接）或 caller（主动连接接收端）
+    pub mode: String,
+    /// 延迟（毫秒）
+    pub latency_ms: u32,
+    /// 加密口令
+    pub passphrase: Option<String>,
+}
+
+impl Default for UdpOptions {
+    fn default() -> UdpOptions {
+        UdpOptions {
+            clients: None,
+            multicast_ttl: 1,
+            multicast_iface: None,
+        }
+    }
+}
+
+impl Default for SrtOptions {
+    fn default() -> SrtOptions {
+        SrtOptions {
+            mode: "listener".to_string(),
+            latency_ms: 125,
+            passphrase: None,
+        }
+    }
+}
+
+/// 创建 MPEG-TS 封装所需的元素（h264parse ! mpegtsmux）
+fn make_mpegts_mux_elements() -> Result<Vec<gst::Element>, OutputError> {
+    // h264parse 负责在每个关键帧前插入 SPS/PPS，接收端可以随时加入
+    let h264parse = gst::ElementFactory::make("h264parse")
+        .property("config-interval", -1i32)
+        .build()
+        .map_err(|e| OutputError::element("h264parse", e))?;
+
+    // 每次输出 7 个 TS 包（1316 字节），正好放进一个 UDP/SRT 数据包
+    let mpegtsmux = gst::ElementFactory::make("mpegtsmux")
+        .property("alignment", 7i32)
+        .build()
+        .map_err(|e| OutputError::element("mpegtsmux", e))?;
+
+    Ok(vec![h264parse, mpegtsmux])
+}
+
+/// 解析额外的 UDP 单播目标
+fn resolve_udp_clients(udp_options: &UdpOptions) -> Result<Vec<ResolvedAddress>, OutputError> {
+    let Some(extra) = &udp_options.clients else {
+        return Ok(Vec::new());
+    };
+    extra
+        .split(',')
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(|client| {
+            HostPort::parse(client)
+                .and_then(|client| client.resolve())
+                .map_err(|source| OutputError::Address {
+                    address: client.to_string(),
+                    source,
+                })
+        })
+        .collect()
+}
+
+/// 创建 udpsink
+/// 组播地址会设置 TTL 和网络接口；配置了额外单播目标时通过 clients 属性同时发送到多个地址
+fn make_udp_sink(
+    address: &ResolvedAddress,
+    extra: &[ResolvedAddress],
+    udp_options: &UdpOptions,
+) -> Result<gst::Element, OutputError> {
+    let mut builder = gst::ElementFactory::make("udpsink")
+        .property("host", address.host())
+        .property("port", address.port() as i32);
+
+    if address.ip().is_multicast() {
+        info!(
+            "{} 是组播地址，TTL: {}",
+            address.ip(),
+            udp_options.multicast_ttl
+        );
+        builder = builder
+            .property("auto-multicast", true)
+            .property("ttl-mc", udp_options.multicast_ttl as i32);
+        if let Some(iface) = &udp_options.multicast_iface {
+            builder = builder.property("multicast-iface", iface.as_str());
+        }
+    }
+
+    if !extra.is_empty() {
+        // clients 属性会替换 host/port 设置的目标，所以要把主目标也放进去
+        // multiudpsink 以最后一个 ':' 分隔端口，IPv6 地址不需要方括号
+        let clients = std::iter::once(address)
+            .chain(extra)
+            .map(|client| format!("{}:{}", client.host(), client.port()))
+            .collect::<Vec<_>>();
+        builder = builder.property("clients", clients.join(",").as_str());
+    }
+
+    builder
+        .build()
+        .map_err(|e| OutputError::element("udpsink", e))
+}
+
+/// 接收组播时 udpsrc 需要的 address 参数
+fn multicast_address_param(address: &ResolvedAddress) -> String {
+    if address.ip().is_multicast() {
+        format!("address={} auto-multicast=true ", address.host())
+    } else {
+        String::new()
+    }
+}
+
+/// 创建 srtsink
+fn make_srt_sink(
+    address: &ResolvedAddress,
+    srt_options: &SrtOptions,
+) -> Result<gst::Element, OutputError> {
+    let uri = match srt_options.mode.as_str() {
+        "listener" => format!("srt://{}?mode=listener", address),
+        "caller" => format!("srt://{}?mode=caller", address),
+        other => {
+            return Err(OutputError::InvalidOption(format!(
+                "不支持的 SRT 模式: {}，支持 listener 或 caller",
+                other
+            )));
+        }
+    };
+
+    let mut builder = gst::ElementFactory::make("srtsink")
+        .property("uri", uri.as_str())
+        .property("latency", srt_options.latency_ms as i32)
+        // 没有接收端连接时不阻塞 pipeline
+        .property("wait-for-connection", false)
+        .property("sync", false);
+
+    if let Some(passphrase) = &srt_options.passphrase {
+        if !(10..=79).contains(&passphrase.len()) {
+            return Err(OutputError::InvalidOption(
+                "SRT 口令长度必须在 10~79 个字符之间".to_string(),
+            ));
+        }
+        builder = builder.property("passphrase", passphrase.as_str());
+    }
+
+    builder
+        .build()
+        .map_err(|e| OutputError::element("srtsink", e))
+}
+
+/// 定期输出 srtsink 的连接统计信息
+/// sink 被销毁后线程自动退出
+fn spawn_srt_stats_logger(sink: &gst::Element, interval: Duration) {
+    let sink = sink.downgrade();
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let Some(sink) = sink.upgrade() else {
+            break;
+        };
+        let stats = sink.property::<gst::Structure>("stats");
+        info!("SRT 统计: {}", stats);
+    });
+}
+
+/// 请求编码器在下一帧输出关键帧（带 SPS/PPS）
+/// element 为编码器上游的元素，事件会随下一个 buffer 向下游传递
+pub fn request_keyframe(element: &gst::Element) {
+    let event = gst_video::DownstreamForceKeyUnitEvent::builder()
+        .all_headers(true)
+        .build();
+    if !element.send_event(event) {
+        debug!("请求关键帧失败");
+    }
+}
@@ -0,0 +1,147 @@
+//! 画面叠加文字：墙上时间、帧序号、会话名称和自定义文字
+//!
+//! 只用于 GStreamer 输出（在编码器前插入 clockoverlay 或 textoverlay），VNC 画面保持原样。
+//! 所有文字放在同一个元素中按行排列，避免多个叠加元素在同一个角落重叠。
+
+use crate::output::OutputError;
+use gst::glib;
+use gst::prelude::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 叠加文字的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OverlayPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl OverlayPosition {
+    /// basetextoverlay 的 halignment 和 valignment
+    fn alignment(self) -> (&'static str, &'static str) {
+        match self {
+            OverlayPosition::TopLeft => ("left", "top"),
+            OverlayPosition::TopRight => ("right", "top"),
+            OverlayPosition::BottomLeft => ("left", "bottom"),
+            OverlayPosition::BottomRight => ("right", "bottom"),
+        }
+    }
+}
+
+/// 叠加选项
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OverlayOptions {
+    /// 墙上时间（本地时区）
+    pub clock: bool,
+    /// 帧序号，从 pipeline 启动时的第一帧开始计数
+    pub frame_number: bool,
+    pub session_name: Option<String>,
+    pub text: Option<String>,
+    pub position: OverlayPosition,
+}
+
+impl OverlayOptions {
+    /// 是否有需要叠加的内容
+    pub fn is_empty(&self) -> bool {
+        !self.clock && !self.frame_number && self.session_name.is_none() && self.text.is_none()
+    }
+
+    /// 固定不变的文字行
+    fn static_lines(&self) -> Vec<String> {
+        self.session_name
+            .iter()
+            .chain(self.text.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+/// 创建叠加元素：需要时间时使用 clockoverlay（时间显示在最后一行末尾），否则使用 textoverlay
+/// 没有需要叠加的内容时返回 None
+pub fn make_overlay(options: &OverlayOptions) -> Result<Option<gst::Element>, OutputError> {
+    if options.is_empty() {
+        return Ok(None);
+    }
+
+    let (name, builder) = if options.clock {
+        let builder = gst::ElementFactory::make("clockoverlay")
+            .property("time-format", "%Y-%m-%d %H:%M:%S");
+        ("clockoverlay", builder)
+    } else {
+        ("textoverlay", gst::ElementFactory::make("textoverlay"))
+    };
+    let (halignment, valignment) = options.position.alignment();
+    let overlay = builder
+        .property_from_str("halignment", halignment)
+        .property_from_str("valignment", valignment)
+        .property_from_str("line-alignment", halignment)
+        .property("shaded-background", true)
+        .property("font-desc", "Monospace 14")
+        .build()
+        .map_err(|e| OutputError::element(name, e))?;
+
+    let lines = options.static_lines();
+    overlay.set_property("text", lines.join("\n"));
+
+    // 帧序号在每个 buffer 到达时更新，文字会用于这一帧的渲染
+    if options.frame_number {
+        // basetextoverlay 的视频输入 pad 名为 video_sink
+        let sink = overlay.static_pad("video_sink").ok_or_else(|| {
+            OutputError::link(
+                "获取叠加元素的视频输入",
+                glib::bool_error!("{} 没有 video_sink pad", name),
+            )
+        })?;
+        let weak = overlay.downgrade();
+        let counter = AtomicU64::new(0);
+        sink.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            if let Some(overlay) = weak.upgrade() {
+                let frame = counter.fetch_add(1, Ordering::Relaxed);
+                let mut text = lines.clone();
+                text.push(format!("#{}", frame));
+                overlay.set_property("text", text.join("\n"));
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    Ok(Some(overlay))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 缺少 pango 插件时跳过
+    fn has_overlay_plugins() -> bool {
+        gst::init().unwrap();
+        ["textoverlay", "clockoverlay"]
+            .iter()
+            .all(|name| gst::ElementFactory::find(name).is_some())
+    }
+
+    #[test]
+    fn empty_options_need_no_overlay() {
+        assert!(make_overlay(&OverlayOptions::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn builds_frame_number_overlay() {
+        if !has_overlay_plugins() {
+            return;
+        }
+        for clock in [false, true] {
+            let options = OverlayOptions {
+                clock,
+                frame_number: true,
+                session_name: Some("ci".to_string()),
+                ..OverlayOptions::default()
+            };
+            let overlay = make_overlay(&options).unwrap().expect("应创建叠加元素");
+            assert!(overlay.static_pad("video_sink").is_some());
+            assert_eq!(overlay.property::<String>("text"), "ci");
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! 帧率控制：按单调时钟上的截止时间取帧，并为 buffer 打上时间戳
+//!
+//! 每一帧的截止时间都是上一帧截止时间加一个帧间隔，而不是处理完后再固定休眠，
+//! 所以取帧、编码的耗时不会累积成帧率漂移。落后超过一帧时跳过错过的帧，不做突发追赶。
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// 统计信息的输出间隔
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct FramePacer {
+    /// 日志中使用的输出名称
+    label: &'static str,
+    frame_duration: Duration,
+    next_deadline: Instant,
+    last_pts: Option<gst::ClockTime>,
+    start_time: Instant,
+    frames: u64,
+    /// 处理完成时已经超过下一帧截止时间的帧数
+    late: u64,
+    /// 因为落后而跳过的帧数
+    dropped: u64,
+    /// 因为画面未变化而没有发送的帧数
+    skipped: u64,
+    last_stats: Instant,
+    last_late: u64,
+    last_dropped: u64,
+}
+
+impl FramePacer {
+    pub fn new(fps: gst::Fraction, label: &'static str) -> FramePacer {
+        let frame_duration =
+            Duration::from_secs_f64(fps.denom() as f64 / fps.numer().max(1) as f64);
+        let now = Instant::now();
+        FramePacer {
+            label,
+            frame_duration,
+            next_deadline: now,
+            last_pts: None,
+            start_time: now,
+            frames: 0,
+            late: 0,
+            dropped: 0,
+            skipped: 0,
+            last_stats: now,
+            last_late: 0,
+            last_dropped: 0,
+        }
+    }
+
+    /// 等待到下一帧的截止时间，等待期间收到停止信号时返回 false
+    pub fn wait(&mut self, stop_rx: &mpsc::Receiver<()>) -> bool {
+        let now = Instant::now();
+        if now > self.next_deadline + self.frame_duration {
+            // 落后超过一帧：跳过错过的帧，从最近的截止时间继续
+            let behind = now - self.next_deadline;
+            let missed = (behind.as_nanos() / self.frame_duration.as_nanos()) as u32;
+            self.dropped += missed as u64;
+            self.next_deadline += self.frame_duration * missed;
+        }
+
+        let timeout = self.next_deadline.saturating_duration_since(now);
+        match stop_rx.recv_timeout(timeout) {
+            Ok(_) | Err(mpsc::RecvTimeoutError::Disconnected) => return false,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+        }
+
+        self.next_deadline += self.frame_duration;
+        true
+    }
+
+    /// 为 buffer 设置 PTS 和时长
+    /// running_time 为 pipeline 时钟上的当前运行时间，保证 PTS 单调递增
+    pub fn stamp(&mut self, buffer: &mut gst::BufferRef, running_time: Option<gst::ClockTime>) {
+        let duration = gst::ClockTime::from_nseconds(self.frame_duration.as_nanos() as u64);
+        let pts = match (running_time, self.last_pts) {
+            (Some(time), Some(last)) if time <= last => last + gst::ClockTime::from_nseconds(1),
+            (Some(time), _) => time,
+            (None, Some(last)) => last + duration,
+            (None, None) => gst::ClockTime::ZERO,
+        };
+        buffer.set_pts(pts);
+        buffer.set_duration(duration);
+        self.last_pts = Some(pts);
+    }
+
+    /// 记录一帧已经发送，并定期输出统计信息
+    pub fn frame_sent(&mut self) {
+        self.frames += 1;
+        if Instant::now() > self.next_deadline {
+            self.late += 1;
+        }
+        self.log_stats();
+    }
+
+    /// 记录一帧因为画面未变化而没有发送
+    pub fn frame_skipped(&mut self) {
+        self.skipped += 1;
+        self.log_stats();
+    }
+
+    fn log_stats(&mut self) {
+        if self.last_stats.elapsed() < STATS_INTERVAL {
+            return;
+        }
+        self.last_stats = Instant::now();
+
+        let fps = self.frames as f64 / self.start_time.elapsed().as_secs_f64();
+        debug!(
+            "{}: 已发送 {} 帧，平均帧率: {:.2} fps，累计迟到 {} 帧，累计丢弃 {} 帧，画面未变化跳过 {} 帧",
+            self.label, self.frames, fps, self.late, self.dropped, self.skipped
+        );
+
+        let late = self.late - self.last_late;
+        let dropped = self.dropped - self.last_dropped;
+        if late > 0 || dropped > 0 {
+            warn!(
+                "{}: 最近 {} 秒迟到 {} 帧，丢弃 {} 帧（取帧或编码跟不上目标帧率）",
+                self.label,
+                STATS_INTERVAL.as_secs(),
+                late,
+                dropped
+            );
+        }
+        self.last_late = self.late;
+        self.last_dropped = self.dropped;
+    }
+}
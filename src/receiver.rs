@@ -0,0 +1,605 @@
+//! 接收 weadless 输出的视频流：UDP 或 TCP 上的 RTP 或 MPEG-TS
+//!
+//! 与服务端的 --protocol 和 --mux 对应，默认值也与服务端一致。解封装后交给 decodebin，
+//! 由它按 caps 中的编码格式选择解包器、解析器和解码器（有硬件解码器时优先使用），
+//! 解码后的画面送入调用方提供的元素（显示窗口、appsink 等）。
+//! RTP 只接收 H.264 的负载类型（96），不支持服务端的 --rtx 和 --fec-percentage。
+//!
+//! 录制时先用 parsebin 解包和解析，解析后的流经过 tee 分别送往 decodebin 和录制文件
+//! （见 recorder），不需要重新编码。
+
+use crate::address::{AddressError, HostPort};
+use crate::bus::Failure;
+use crate::recorder::Recorder;
+use crate::rtp::H264_PT;
+use gst::glib;
+use gst::prelude::*;
+use gst::MessageView;
+use gst_app::{AppSink, AppSinkCallbacks};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::{info, warn};
+
+/// 传输协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Transport {
+    #[default]
+    Udp,
+    Tcp,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::Udp => write!(f, "udp"),
+            Transport::Tcp => write!(f, "tcp"),
+        }
+    }
+}
+
+/// 封装格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Mux {
+    #[default]
+    Rtp,
+    Mpegts,
+}
+
+impl fmt::Display for Mux {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mux::Rtp => write!(f, "rtp"),
+            Mux::Mpegts => write!(f, "mpegts"),
+        }
+    }
+}
+
+/// 接收选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiverOptions {
+    pub transport: Transport,
+    /// udp 为本地接收地址（即服务端的 --output-address），tcp 为服务端地址
+    pub address: String,
+    pub mux: Mux,
+    /// RTP 的编码名称，决定使用哪个解包器
+    pub encoding_name: String,
+    /// RTP 抖动缓冲（毫秒）
+    pub jitter_latency: u32,
+    /// 把流不经重新编码写入这个文件（.mp4 或 .mkv）
+    pub record: Option<PathBuf>,
+}
+
+impl Default for ReceiverOptions {
+    fn default() -> ReceiverOptions {
+        ReceiverOptions {
+            transport: Transport::Udp,
+            address: "127.0.0.1:5000".to_string(),
+            mux: Mux::Rtp,
+            encoding_name: "H264".to_string(),
+            jitter_latency: 200,
+            record: None,
+        }
+    }
+}
+
+/// 接收错误
+#[derive(Debug)]
+pub enum ReceiverError {
+    /// 无法创建 GStreamer 元素，通常是缺少插件
+    Element {
+        name: &'static str,
+        source: glib::BoolError,
+    },
+    /// 无法把元素加入 pipeline 或链接元素
+    Link {
+        what: &'static str,
+        source: glib::BoolError,
+    },
+    /// 地址无效
+    Address {
+        address: String,
+        source: AddressError,
+    },
+    /// pipeline 运行时出错（包括无法进入 Playing 状态）
+    Pipeline(Failure),
+    /// 无法写入录制文件
+    Record { path: PathBuf, message: String },
+    /// 既没有解码后的 sink 元素，也没有录制文件
+    NoOutput,
+    /// 流没有通过检查，包含失败原因和未达到的阈值
+    CheckFailed(Vec<String>),
+}
+
+impl ReceiverError {
+    fn element(name: &'static str, source: glib::BoolError) -> ReceiverError {
+        ReceiverError::Element { name, source }
+    }
+
+    fn link(what: &'static str, source: glib::BoolError) -> ReceiverError {
+        ReceiverError::Link { what, source }
+    }
+
+    /// 给用户的处理建议
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            ReceiverError::Element { name, .. } => Some(format!(
+                "使用 gst-inspect-1.0 {} 检查对应的 GStreamer 插件是否已安装",
+                name
+            )),
+            ReceiverError::Address { .. } => Some(
+                "地址格式为 host:port，例如 127.0.0.1:5000、[::1]:5000 或 [fe80::1%eth0]:5000"
+                    .to_string(),
+            ),
+            ReceiverError::Pipeline(_) => Some(
+                "确认服务端的 --protocol 和 --mux 与接收端一致，tcp 需要服务端已经启动；\
+                设置 GST_DEBUG=3 查看详细信息"
+                    .to_string(),
+            ),
+            ReceiverError::Record { .. } => {
+                Some("录制文件的扩展名必须是 .mp4 或 .mkv，并确认目录存在且可写".to_string())
+            }
+            ReceiverError::NoOutput => {
+                Some("只录制时需要设置录制文件（--record-only 需要 --record）".to_string())
+            }
+            ReceiverError::CheckFailed(_) => {
+                Some("用 --timeout 延长检查时间，或者设置 GST_DEBUG=3 查看详细信息".to_string())
+            }
+            ReceiverError::Link { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for ReceiverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReceiverError::Element { name, source } => write!(f, "无法创建 {}: {}", name, source),
+            ReceiverError::Link { what, source } => write!(f, "无法{}: {}", what, source),
+            ReceiverError::Address { address, source } => {
+                write!(f, "接收地址 {} 无效: {}", address, source)
+            }
+            ReceiverError::Pipeline(failure) => write!(f, "{}", failure),
+            ReceiverError::Record { path, message } => {
+                write!(f, "无法录制到 {}: {}", path.display(), message)
+            }
+            ReceiverError::NoOutput => write!(f, "接收 pipeline 需要 sink 元素或录制文件"),
+            ReceiverError::CheckFailed(failures) => {
+                write!(f, "检查没有通过: {}", failures.join("；"))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReceiverError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReceiverError::Element { source, .. } | ReceiverError::Link { source, .. } => {
+                Some(source)
+            }
+            ReceiverError::Address { source, .. } => Some(source),
+            ReceiverError::Pipeline(_)
+            | ReceiverError::Record { .. }
+            | ReceiverError::NoOutput
+            | ReceiverError::CheckFailed(_) => None,
+        }
+    }
+}
+
+/// 累计的接收统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReceiverStats {
+    /// 解码后的帧数（只录制时为录制的帧数）
+    pub frames: u64,
+    /// 收到的字节数（包括 RTP 或 TS 封装）
+    pub bytes: u64,
+    /// 抖动缓冲判定丢失的 RTP 包数
+    pub lost: u64,
+    /// 解码器报告的错误数（解码器会跳过无法解码的帧，错误太多时 pipeline 才会出错）
+    pub decode_errors: u64,
+}
+
+impl ReceiverStats {
+    /// 与较早的统计相减，得到这段时间内的增量
+    pub fn since(&self, earlier: &ReceiverStats) -> ReceiverStats {
+        ReceiverStats {
+            frames: self.frames.saturating_sub(earlier.frames),
+            bytes: self.bytes.saturating_sub(earlier.bytes),
+            lost: self.lost.saturating_sub(earlier.lost),
+            decode_errors: self.decode_errors.saturating_sub(earlier.decode_errors),
+        }
+    }
+}
+
+/// 接收 pipeline 的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiverState {
+    Running,
+    /// 流已结束（例如 TCP 连接被服务端关闭）
+    Eos,
+}
+
+/// 正在运行的接收 pipeline
+pub struct Receiver {
+    pipeline: gst::Pipeline,
+    jitterbuffer: Option<gst::Element>,
+    frames: Arc<AtomicU64>,
+    bytes: Arc<AtomicU64>,
+    decode_errors: AtomicU64,
+    recorder: Option<Arc<Mutex<Recorder>>>,
+}
+
+impl Receiver {
+    /// 创建并启动接收 pipeline，解码后的画面送入 sink
+    /// sink 中的元素按顺序链接，第一个元素必须接受 video/x-raw；
+    /// sink 为空时不解码，只录制（需要 options.record）
+    pub fn start(
+        options: &ReceiverOptions,
+        sink: &[gst::Element],
+    ) -> Result<Receiver, ReceiverError> {
+        let recorder = options.record.as_deref().map(Recorder::new).transpose()?;
+        let first = sink.first().cloned();
+        let pipeline = gst::Pipeline::new();
+        let mut elements = make_source_elements(options)?;
+        let jitterbuffer = match options.mux {
+            Mux::Rtp => {
+                let jitterbuffer = gst::ElementFactory::make("rtpjitterbuffer")
+                    .property("latency", options.jitter_latency)
+                    .build()
+                    .map_err(|e| ReceiverError::element("rtpjitterbuffer", e))?;
+                elements.push(jitterbuffer.clone());
+                Some(jitterbuffer)
+            }
+            Mux::Mpegts => None,
+        };
+        // 不录制时直接解码，录制时先解析，解码在 tee 之后
+        let entry = match (&recorder, &first) {
+            (Some(_), _) => {
+                let parsebin = make_element("parsebin")?;
+                log_chosen_elements(&parsebin);
+                parsebin
+            }
+            (None, Some(first)) => make_decodebin(first)?,
+            (None, None) => return Err(ReceiverError::NoOutput),
+        };
+        elements.push(entry.clone());
+
+        pipeline
+            .add_many(elements.iter().chain(sink))
+            .map_err(|e| ReceiverError::link("添加元素到 pipeline", e))?;
+        gst::Element::link_many(&elements).map_err(|e| ReceiverError::link("链接元素", e))?;
+        gst::Element::link_many(sink).map_err(|e| ReceiverError::link("链接元素", e))?;
+        let (recorder, recorder_sink) = match recorder {
+            Some(recorder) => {
+                let (recorder, appsink) =
+                    branch_recording(&pipeline, &entry, first.as_ref(), recorder)?;
+                (Some(recorder), Some(appsink))
+            }
+            None => (None, None),
+        };
+
+        // 收到的字节数，在源元素的输出上统计
+        let bytes = Arc::new(AtomicU64::new(0));
+        let src_pad = elements[0].static_pad("src").expect("源元素没有 src pad");
+        let counter = bytes.clone();
+        src_pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST,
+            move |_, info| {
+                let size = match &info.data {
+                    Some(gst::PadProbeData::Buffer(buffer)) => buffer.size(),
+                    Some(gst::PadProbeData::BufferList(list)) => list.calculate_size(),
+                    _ => 0,
+                };
+                counter.fetch_add(size as u64, Ordering::Relaxed);
+                gst::PadProbeReturn::Ok
+            },
+        );
+
+        // 解码后的帧数，只录制时统计录制的帧数
+        let frames = Arc::new(AtomicU64::new(0));
+        let Some(counted) = first.or_else(|| recorder_sink.map(|appsink| appsink.upcast())) else {
+            return Err(ReceiverError::NoOutput);
+        };
+        let sink_pad = counted.static_pad("sink").expect("sink 元素没有 sink pad");
+        let counter = frames.clone();
+        sink_pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            gst::PadProbeReturn::Ok
+        });
+
+        // 输入流的 caps
+        let entry_sink = entry
+            .static_pad("sink")
+            .expect("decodebin 或 parsebin 没有 sink pad");
+        entry_sink.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, |_, info| {
+            if let Some(gst::PadProbeData::Event(event)) = &info.data {
+                if let gst::EventView::Caps(caps) = event.view() {
+                    info!("输入流: {}", caps.caps());
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+
+        pipeline.set_state(gst::State::Playing).map_err(|e| {
+            ReceiverError::Pipeline(Failure {
+                source: String::new(),
+                message: format!("无法启动接收 pipeline: {}", e),
+                from_encoder: false,
+            })
+        })?;
+        info!(
+            "正在接收 {}://{}（{}）",
+            options.transport, options.address, options.mux
+        );
+        if let Some(path) = &options.record {
+            info!("录制到 {}，收到关键帧后开始写入", path.display());
+        }
+
+        Ok(Receiver {
+            pipeline,
+            jitterbuffer,
+            frames,
+            bytes,
+            decode_errors: AtomicU64::new(0),
+            recorder,
+        })
+    }
+
+    pub fn pipeline(&self) -> &gst::Pipeline {
+        &self.pipeline
+    }
+
+    /// 当前的累计统计
+    pub fn stats(&self) -> ReceiverStats {
+        let lost = self
+            .jitterbuffer
+            .as_ref()
+            .and_then(|jitterbuffer| {
+                jitterbuffer
+                    .property::<gst::Structure>("stats")
+                    .get::<u64>("num-lost")
+                    .ok()
+            })
+            .unwrap_or(0);
+        ReceiverStats {
+            frames: self.frames.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            lost,
+            decode_errors: self.decode_errors.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 取出总线上积压的消息，pipeline 出错时返回错误
+    pub fn poll(&self) -> Result<ReceiverState, ReceiverError> {
+        if let Some(recorder) = &self.recorder {
+            if let Some(e) = lock(recorder).failure() {
+                return Err(e);
+            }
+        }
+        let Some(bus) = self.pipeline.bus() else {
+            return Ok(ReceiverState::Running);
+        };
+        let mut state = ReceiverState::Running;
+        while let Some(message) = bus.pop() {
+            let source = message
+                .src()
+                .map(|src| src.path_string().to_string())
+                .unwrap_or_default();
+            match message.view() {
+                MessageView::Error(err) => {
+                    return Err(ReceiverError::Pipeline(Failure {
+                        source,
+                        message: err.error().to_string(),
+                        from_encoder: false,
+                    }));
+                }
+                MessageView::Warning(warning) => {
+                    if warning.error().matches(gst::StreamError::Decode) {
+                        self.decode_errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                    warn!(source = %source, warning = %warning.error(), "接收 pipeline 警告");
+                }
+                MessageView::Eos(_) => state = ReceiverState::Eos,
+                _ => {}
+            }
+        }
+        Ok(state)
+    }
+
+    /// 停止 pipeline，结束正在写入的录制文件
+    pub fn stop(self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+        if let Some(recorder) = &self.recorder {
+            lock(recorder).finish();
+        }
+    }
+}
+
+/// 锁中毒时继续使用里面的数据，停止时仍然要结束录制文件
+fn lock(recorder: &Mutex<Recorder>) -> MutexGuard<'_, Recorder> {
+    recorder.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn make_element(name: &'static str) -> Result<gst::Element, ReceiverError> {
+    gst::ElementFactory::make(name)
+        .build()
+        .map_err(|e| ReceiverError::element(name, e))
+}
+
+/// 输出 decodebin 或 parsebin 选中的解包器、解析器和解码器
+fn log_chosen_elements(bin: &gst::Element) {
+    bin.connect("deep-element-added", false, |values| {
+        if let Ok(element) = values[2].get::<gst::Element>() {
+            if let Some(factory) = element.factory() {
+                let klass = factory.klass();
+                if klass.contains("Depayloader")
+                    || klass.contains("Decoder")
+                    || klass.contains("Parser")
+                    || klass.contains("Demuxer")
+                {
+                    info!("使用 {}（{}）", factory.name(), klass);
+                }
+            }
+        }
+        None
+    });
+}
+
+/// decodebin，解码后的视频链接到 sink
+fn make_decodebin(sink: &gst::Element) -> Result<gst::Element, ReceiverError> {
+    let decodebin = make_element("decodebin")?;
+    log_chosen_elements(&decodebin);
+
+    // 只链接视频，其他流（例如 TS 中的其他节目）不处理
+    let sink = sink.clone();
+    decodebin.connect_pad_added(move |_, pad| {
+        let caps = pad.current_caps().unwrap_or_else(|| pad.query_caps(None));
+        let is_video = caps
+            .structure(0)
+            .is_some_and(|s| s.name().starts_with("video/x-raw"));
+        if !is_video {
+            return;
+        }
+        info!("解码后的画面: {}", caps);
+        let sink_pad = sink.static_pad("sink").expect("sink 元素没有 sink pad");
+        if sink_pad.is_linked() {
+            warn!("流中有多个视频，只显示第一个");
+            return;
+        }
+        if let Err(e) = pad.link(&sink_pad) {
+            warn!("无法链接解码器输出: {:?}", e);
+        }
+    });
+    Ok(decodebin)
+}
+
+/// 切换录制文件时等待文件尾写完，期间的数据缓存在队列中，不阻塞显示
+const RECORD_QUEUE_TIME: gst::ClockTime = gst::ClockTime::from_seconds(10);
+
+/// parsebin 之后的 tee：一路解码后送入 sink（如果有），一路写入录制文件
+fn branch_recording(
+    pipeline: &gst::Pipeline,
+    parsebin: &gst::Element,
+    sink: Option<&gst::Element>,
+    recorder: Recorder,
+) -> Result<(Arc<Mutex<Recorder>>, AppSink), ReceiverError> {
+    let tee = make_element("tee")?;
+    let queue = gst::ElementFactory::make("queue")
+        .property("max-size-buffers", 0u32)
+        .property("max-size-bytes", 0u32)
+        .property("max-size-time", RECORD_QUEUE_TIME.nseconds())
+        .build()
+        .map_err(|e| ReceiverError::element("queue", e))?;
+    let appsink = AppSink::builder().sync(false).build();
+    let elements = [tee.clone(), queue, appsink.clone().upcast()];
+    pipeline
+        .add_many(&elements)
+        .map_err(|e| ReceiverError::link("添加元素到 pipeline", e))?;
+    gst::Element::link_many(&elements).map_err(|e| ReceiverError::link("链接元素", e))?;
+
+    if let Some(sink) = sink {
+        let queue = make_element("queue")?;
+        let decodebin = make_decodebin(sink)?;
+        pipeline
+            .add_many([&queue, &decodebin])
+            .map_err(|e| ReceiverError::link("添加元素到 pipeline", e))?;
+        gst::Element::link_many([&tee, &queue, &decodebin])
+            .map_err(|e| ReceiverError::link("链接元素", e))?;
+    }
+
+    let recorder = Arc::new(Mutex::new(recorder));
+    let writer = recorder.clone();
+    appsink.set_callbacks(
+        AppSinkCallbacks::builder()
+            .new_sample(move |appsink| {
+                let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                lock(&writer).push(&sample);
+                Ok(gst::FlowSuccess::Ok)
+            })
+            .build(),
+    );
+
+    // 解析后的视频送入 tee，只处理第一个视频
+    let tee_sink = tee.static_pad("sink").expect("tee 没有 sink pad");
+    parsebin.connect_pad_added(move |_, pad| {
+        let caps = pad.current_caps().unwrap_or_else(|| pad.query_caps(None));
+        let is_video = caps
+            .structure(0)
+            .is_some_and(|s| s.name().starts_with("video/"));
+        if !is_video {
+            return;
+        }
+        if tee_sink.is_linked() {
+            warn!("流中有多个视频，只处理第一个");
+            return;
+        }
+        info!("解析后的流: {}", caps);
+        if let Err(e) = pad.link(&tee_sink) {
+            warn!("无法链接解析器输出: {:?}", e);
+        }
+    });
+    Ok((recorder, appsink))
+}
+
+/// 源元素，以及 TCP 上 RTP 的解帧元素
+fn make_source_elements(options: &ReceiverOptions) -> Result<Vec<gst::Element>, ReceiverError> {
+    let address = HostPort::parse(&options.address)
+        .and_then(|address| address.resolve())
+        .map_err(|source| ReceiverError::Address {
+            address: options.address.clone(),
+            source,
+        })?;
+
+    let rtp_caps = |name: &str| {
+        gst::Caps::builder(name)
+            .field("media", "video")
+            .field("clock-rate", 90000i32)
+            .field("encoding-name", options.encoding_name.to_uppercase())
+            .field("payload", H264_PT as i32)
+            .build()
+    };
+    let ts_caps = gst::Caps::builder("video/mpegts")
+        .field("systemstream", true)
+        .build();
+
+    match options.transport {
+        Transport::Udp => {
+            let caps = match options.mux {
+                Mux::Rtp => rtp_caps("application/x-rtp"),
+                Mux::Mpegts => ts_caps,
+            };
+            let mut builder = gst::ElementFactory::make("udpsrc")
+                .property("address", address.ip().to_string())
+                .property("port", address.port() as i32)
+                .property("caps", caps);
+            if address.ip().is_multicast() {
+                builder = builder.property("auto-multicast", true);
+            }
+            let udpsrc = builder
+                .build()
+                .map_err(|e| ReceiverError::element("udpsrc", e))?;
+            Ok(vec![udpsrc])
+        }
+        Transport::Tcp => {
+            // 服务端在 TCP 上按 RFC 4571 给每个 RTP 包加上长度前缀
+            let caps = match options.mux {
+                Mux::Rtp => rtp_caps("application/x-rtp-stream"),
+                Mux::Mpegts => ts_caps,
+            };
+            let tcpclientsrc = gst::ElementFactory::make("tcpclientsrc")
+                .property("host", address.host())
+                .property("port", address.port() as i32)
+                .property("caps", caps)
+                .build()
+                .map_err(|e| ReceiverError::element("tcpclientsrc", e))?;
+            let mut elements = vec![tcpclientsrc];
+            if options.mux == Mux::Rtp {
+                let depay = gst::ElementFactory::make("rtpstreamdepay")
+                    .build()
+                    .map_err(|e| ReceiverError::element("rtpstreamdepay", e))?;
+                elements.push(depay);
+            }
+            Ok(elements)
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! 把接收到的视频流不经重新编码写入 MP4 或 MKV 文件
+//!
+//! 每个文件使用一个 appsrc ! 解析器 ! 封装器 ! filesink pipeline，从关键帧开始写入。
+//! 流的 caps 变化（分辨率、编码参数等）时结束当前文件并开始下一个，
+//! 文件名依次为 name.mp4、name-1.mp4、name-2.mp4……
+
+use crate::receiver::ReceiverError;
+use gst::prelude::*;
+use gst::MessageView;
+use gst_app::AppSrc;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// 等待封装器写完文件尾的最长时间
+const FINISH_TIMEOUT: gst::ClockTime = gst::ClockTime::from_seconds(5);
+
+/// 文件格式，由扩展名决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Mp4,
+    Matroska,
+}
+
+impl Container {
+    fn from_path(path: &Path) -> Option<Container> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "mp4" => Some(Container::Mp4),
+            "mkv" => Some(Container::Matroska),
+            _ => None,
+        }
+    }
+
+    fn muxer(self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4mux",
+            Container::Matroska => "matroskamux",
+        }
+    }
+}
+
+/// 把流转换为封装器接受的格式（例如 H.264 的 byte-stream 转为 avc）
+fn parser_for(caps: &gst::Caps) -> Option<&'static str> {
+    match caps.structure(0)?.name().as_str() {
+        "video/x-h264" => Some("h264parse"),
+        "video/x-h265" => Some("h265parse"),
+        "video/x-av1" => Some("av1parse"),
+        _ => None,
+    }
+}
+
+/// 正在写入的文件
+struct RecordFile {
+    path: PathBuf,
+    pipeline: gst::Pipeline,
+    appsrc: AppSrc,
+    caps: gst::Caps,
+    /// 第一帧的时间戳，文件中的时间从 0 开始
+    base: gst::ClockTime,
+    frames: u64,
+}
+
+impl RecordFile {
+    fn open(
+        path: PathBuf,
+        container: Container,
+        caps: &gst::Caps,
+        base: gst::ClockTime,
+    ) -> Result<RecordFile, String> {
+        let appsrc = AppSrc::builder()
+            .caps(caps)
+            .format(gst::Format::Time)
+            .build();
+        let mut elements = vec![appsrc.clone().upcast::<gst::Element>()];
+        if let Some(parser) = parser_for(caps) {
+            elements.push(make(parser)?);
+        }
+        elements.push(make(container.muxer())?);
+        elements.push(
+            gst::ElementFactory::make("filesink")
+                .property("location", path.to_string_lossy().to_string())
+                .build()
+                .map_err(|e| format!("无法创建 filesink: {}", e))?,
+        );
+
+        let pipeline = gst::Pipeline::new();
+        pipeline
+            .add_many(&elements)
+            .map_err(|e| format!("无法添加元素到 pipeline: {}", e))?;
+        gst::Element::link_many(&elements).map_err(|e| format!("无法链接元素: {}", e))?;
+        pipeline
+            .set_state(gst::State::Playing)
+            .map_err(|e| format!("无法启动录制 pipeline: {}", e))?;
+        info!("开始录制 {}（{}）", path.display(), caps);
+
+        Ok(RecordFile {
+            path,
+            pipeline,
+            appsrc,
+            caps: caps.clone(),
+            base,
+            frames: 0,
+        })
+    }
+
+    fn push(&mut self, mut buffer: gst::Buffer) -> Result<(), String> {
+        {
+            let buffer = buffer.make_mut();
+            let pts = buffer.pts().map(|pts| pts.saturating_sub(self.base));
+            let dts = buffer.dts().map(|dts| dts.saturating_sub(self.base));
+            buffer.set_pts(pts);
+            buffer.set_dts(dts);
+        }
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|e| format!("写入失败: {:?}", e))?;
+        self.frames += 1;
+
+        // 封装器或 filesink 出错（例如磁盘已满）
+        let error = self
+            .pipeline
+            .bus()
+            .and_then(|bus| bus.pop_filtered(&[gst::MessageType::Error]));
+        if let Some(message) = error {
+            if let MessageView::Error(err) = message.view() {
+                return Err(err.error().to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// 发送 EOS，等待封装器写完文件尾（MP4 没有文件尾时无法播放）
+    fn finish(self) {
+        let _ = self.appsrc.end_of_stream();
+        let message = self.pipeline.bus().and_then(|bus| {
+            bus.timed_pop_filtered(
+                FINISH_TIMEOUT,
+                &[gst::MessageType::Eos, gst::MessageType::Error],
+            )
+        });
+        match message.as_ref().map(|message| message.view()) {
+            Some(MessageView::Eos(_)) => {
+                info!("已保存 {}（{} 帧）", self.path.display(), self.frames)
+            }
+            Some(MessageView::Error(err)) => {
+                warn!("{} 没有正确结束: {}", self.path.display(), err.error())
+            }
+            _ => warn!(
+                "等待 {} 写入完成超时，文件可能无法播放",
+                self.path.display()
+            ),
+        }
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+fn make(name: &str) -> Result<gst::Element, String> {
+    gst::ElementFactory::make(name)
+        .build()
+        .map_err(|e| format!("无法创建 {}: {}", name, e))
+}
+
+/// 录制接收到的流，在接收 pipeline 的 appsink 回调中使用
+pub(crate) struct Recorder {
+    path: PathBuf,
+    container: Container,
+    /// 已经打开过的文件数，用于生成下一个文件名
+    opened: u32,
+    file: Option<RecordFile>,
+    /// 出错的文件和原因，出错后不再录制
+    failure: Option<(PathBuf, String)>,
+}
+
+impl Recorder {
+    /// 检查文件格式和封装器，第一个文件在收到第一个关键帧时创建
+    pub(crate) fn new(path: &Path) -> Result<Recorder, ReceiverError> {
+        let container = Container::from_path(path).ok_or_else(|| ReceiverError::Record {
+            path: path.to_path_buf(),
+            message: "不支持的文件格式".to_string(),
+        })?;
+        let muxer = container.muxer();
+        gst::ElementFactory::make(muxer)
+            .build()
+            .map_err(|source| ReceiverError::Element {
+                name: muxer,
+                source,
+            })?;
+        Ok(Recorder {
+            path: path.to_path_buf(),
+            container,
+            opened: 0,
+            file: None,
+            failure: None,
+        })
+    }
+
+    pub(crate) fn push(&mut self, sample: &gst::Sample) {
+        if self.failure.is_some() {
+            return;
+        }
+        let (Some(caps), Some(buffer)) = (sample.caps_owned(), sample.buffer_owned()) else {
+            return;
+        };
+
+        if self.file.as_ref().is_some_and(|file| file.caps != caps) {
+            info!("流的格式已变化，切换到新文件");
+            self.finish();
+        }
+        if self.file.is_none() {
+            // 新文件从关键帧开始，否则开头的画面无法解码
+            if buffer.flags().contains(gst::BufferFlags::DELTA_UNIT) {
+                return;
+            }
+            let base = buffer
+                .dts()
+                .or(buffer.pts())
+                .unwrap_or(gst::ClockTime::ZERO);
+            let path = self.next_path();
+            match RecordFile::open(path.clone(), self.container, &caps, base) {
+                Ok(file) => self.file = Some(file),
+                Err(message) => {
+                    self.failure = Some((path, message));
+                    return;
+                }
+            }
+        }
+
+        let Some(file) = self.file.as_mut() else {
+            return;
+        };
+        if let Err(message) = file.push(buffer) {
+            let path = file.path.clone();
+            self.finish();
+            self.failure = Some((path, message));
+        }
+    }
+
+    /// 录制出错时返回错误
+    pub(crate) fn failure(&self) -> Option<ReceiverError> {
+        self.failure
+            .as_ref()
+            .map(|(path, message)| ReceiverError::Record {
+                path: path.clone(),
+                message: message.clone(),
+            })
+    }
+
+    /// 结束当前文件
+    pub(crate) fn finish(&mut self) {
+        if let Some(file) = self.file.take() {
+            file.finish();
+        }
+    }
+
+    /// 第一个文件使用指定的文件名，之后在扩展名前加上序号
+    fn next_path(&mut self) -> PathBuf {
+        let path = if self.opened == 0 {
+            self.path.clone()
+        } else {
+            let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = self.path.extension().unwrap_or_default().to_string_lossy();
+            self.path
+                .with_file_name(format!("{}-{}.{}", stem, self.opened, extension))
+        };
+        self.opened += 1;
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorder(path: &str) -> Recorder {
+        let path = PathBuf::from(path);
+        Recorder {
+            container: Container::from_path(&path).unwrap(),
+            path,
+            opened: 0,
+            file: None,
+            failure: None,
+        }
+    }
+
+    #[test]
+    fn numbers_following_files() {
+        let mut recorder = recorder("out/name.mp4");
+        assert_eq!(recorder.next_path(), PathBuf::from("out/name.mp4"));
+        assert_eq!(recorder.next_path(), PathBuf::from("out/name-1.mp4"));
+        assert_eq!(recorder.next_path(), PathBuf::from("out/name-2.mp4"));
+    }
+
+    #[test]
+    fn keeps_extension_case_in_file_names() {
+        let mut recorder = recorder("name.MKV");
+        assert_eq!(recorder.next_path(), PathBuf::from("name.MKV"));
+        assert_eq!(recorder.next_path(), PathBuf::from("name-1.MKV"));
+    }
+
+    #[test]
+    fn picks_container_from_extension() {
+        assert_eq!(
+            Container::from_path(Path::new("a.mp4")),
+            Some(Container::Mp4)
+        );
+        assert_eq!(
+            Container::from_path(Path::new("a.mkv")),
+            Some(Container::Matroska)
+        );
+        assert_eq!(
+            Container::from_path(Path::new("a.MP4")),
+            Some(Container::Mp4)
+        );
+        assert_eq!(
+            Container::from_path(Path::new("a.Mkv")),
+            Some(Container::Matroska)
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_extensions() {
+        for path in ["a.avi", "a.ts", "a.webm", "a", "a.mp4.tmp", ".mkv"] {
+            assert_eq!(Container::from_path(Path::new(path)), None, "{}", path);
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! RTP 会话（rtpbin）：RTCP、重传（RTX）和前向纠错（ULPFEC/RED）
+//!
+//! 端口约定（与 GStreamer 文档中的示例一致）：
+//!   - port     发送 RTP
+//!   - port + 1 发送 RTCP 到接收端
+//!   - port + 5 在本机接收接收端发回的 RTCP（接收报告、NACK）
+//!
+//! 配置了额外的单播目标时，每个目标都按自己的端口收发 RTCP。
+
+use crate::address::ResolvedAddress;
+use crate::output::OutputError;
+use gst::glib;
+use gst::prelude::*;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// H.264 RTP 负载类型
+pub const H264_PT: u32 = 96;
+/// RTX 重传负载类型
+pub const RTX_PT: u32 = 97;
+/// ULPFEC 负载类型
+pub const ULPFEC_PT: u32 = 122;
+/// RED 负载类型
+pub const RED_PT: u32 = 123;
+
+/// 发送 RTCP 的端口相对 RTP 端口的偏移
+const RTCP_SEND_OFFSET: u16 = 1;
+/// 接收 RTCP 的端口相对 RTP 端口的偏移
+const RTCP_RECV_OFFSET: u16 = 5;
+
+/// RTP 会话选项
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RtpOptions {
+    /// 启用 RTX 重传（需要接收端发送 NACK）
+    pub rtx: bool,
+    /// ULPFEC 冗余百分比，0 表示关闭
+    pub fec_percentage: u32,
+    /// 使用 RED 封装 FEC 包
+    pub fec_red: bool,
+}
+
+/// 从 RTCP 接收报告中得到的链路质量
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RtcpReport {
+    /// 最近一个报告周期的丢包率（0.0~1.0）
+    pub fraction_lost: f64,
+    /// 累计丢包数
+    pub packets_lost: i32,
+    /// 到达间隔抖动（毫秒）
+    pub jitter_ms: f64,
+    /// 往返时延（毫秒），接收报告中没有 LSR（接收端还没有收到 SR）时为 None
+    pub rtt_ms: Option<f64>,
+}
+
+impl RtcpReport {
+    /// 两份报告中各项指标较差的值
+    fn worst(self, other: RtcpReport) -> RtcpReport {
+        RtcpReport {
+            fraction_lost: self.fraction_lost.max(other.fraction_lost),
+            packets_lost: self.packets_lost.max(other.packets_lost),
+            jitter_ms: self.jitter_ms.max(other.jitter_ms),
+            rtt_ms: match (self.rtt_ms, other.rtt_ms) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            },
+        }
+    }
+}
+
+impl fmt::Display for RtcpReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "丢包率 {:.1}%，累计丢包 {}，抖动 {:.1} ms，",
+            self.fraction_lost * 100.0,
+            self.packets_lost,
+            self.jitter_ms
+        )?;
+        match self.rtt_ms {
+            Some(rtt) => write!(f, "RTT {:.1} ms", rtt),
+            None => write!(f, "RTT 未知"),
+        }
+    }
+}
+
+/// 把 payloader 接入 rtpbin，并创建 RTCP 的发送和接收端
+/// payloader 和 rtp_sink 必须已经添加到 pipeline 中
+/// 返回 rtpbin
+pub fn link_udp_session(
+    pipeline: &gst::Pipeline,
+    payloader: &gst::Element,
+    rtp_sink: &gst::Element,
+    address: &ResolvedAddress,
+    extra: &[ResolvedAddress],
+    options: &RtpOptions,
+) -> Result<gst::Element, OutputError> {
+    let (rtcp_send_port, rtcp_recv_port) = rtcp_ports(address.port())?;
+    // 每个目标的 RTCP 发送地址，以及需要监听的 RTCP 接收端口（同一端口只监听一次）
+    let mut rtcp_clients = vec![format!("{}:{}", address.host(), rtcp_send_port)];
+    let mut rtcp_recv_ports = vec![rtcp_recv_port];
+    for target in extra {
+        let (send_port, recv_port) = rtcp_ports(target.port())?;
+        rtcp_clients.push(format!("{}:{}", target.host(), send_port));
+        if !rtcp_recv_ports.contains(&recv_port) {
+            rtcp_recv_ports.push(recv_port);
+        }
+    }
+
+    // NACK 反馈需要 AVPF profile
+    let rtpbin = gst::ElementFactory::make("rtpbin")
+        .property_from_str("rtp-profile", if options.rtx { "avpf" } else { "avp" })
+        .build()
+        .map_err(|e| OutputError::element("rtpbin", e))?;
+
+    if options.rtx {
+        rtpbin.connect("request-aux-sender", false, |args| {
+            let session = args[1].get::<u32>().expect("session 参数类型错误");
+            let sender = make_rtx_sender(session)
+                .map_err(|e| error!("无法创建 RTX 发送端: {:?}", e))
+                .ok();
+            Some(sender.to_value())
+        });
+    }
+
+    if options.fec_percentage > 0 {
+        let fec_percentage = options.fec_percentage;
+        let fec_red = options.fec_red;
+        rtpbin.connect("request-fec-encoder", false, move |_args| {
+            let encoder = make_fec_encoder(fec_percentage, fec_red)
+                .map_err(|e| error!("无法创建 FEC 编码器: {:?}", e))
+                .ok();
+            Some(encoder.to_value())
+        });
+    }
+
+    let mut rtcp_sink = gst::ElementFactory::make("udpsink")
+        .property("host", address.host())
+        .property("port", rtcp_send_port as i32)
+        .property("sync", false)
+        .property("async", false);
+    if address.ip().is_multicast() {
+        rtcp_sink = rtcp_sink.property("auto-multicast", true);
+    }
+    if !extra.is_empty() {
+        // 与 RTP 的 udpsink 一样，clients 属性会替换 host/port 设置的目标
+        rtcp_sink = rtcp_sink.property("clients", rtcp_clients.join(",").as_str());
+    }
+    let rtcp_sink = rtcp_sink
+        .build()
+        .map_err(|e| OutputError::element("udpsink", e))?;
+
+    let any_address = if address.ip().is_ipv6() { "::" } else { "0.0.0.0" };
+    let rtcp_srcs = rtcp_recv_ports
+        .iter()
+        .map(|&port| {
+            gst::ElementFactory::make("udpsrc")
+                .property("address", any_address)
+                .property("port", port as i32)
+                .build()
+                .map_err(|e| OutputError::element("udpsrc", e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    // 多个接收端口经过 funnel 汇合到同一个 RTCP 输入
+    let funnel = if rtcp_srcs.len() > 1 {
+        Some(
+            gst::ElementFactory::make("funnel")
+                .build()
+                .map_err(|e| OutputError::element("funnel", e))?,
+        )
+    } else {
+        None
+    };
+
+    pipeline
+        .add_many([&rtpbin, &rtcp_sink])
+        .map_err(|e| OutputError::link("添加 rtpbin 到 pipeline", e))?;
+    pipeline
+        .add_many(rtcp_srcs.iter().chain(funnel.iter()))
+        .map_err(|e| OutputError::link("添加 RTCP udpsrc 到 pipeline", e))?;
+
+    // link_pads 会按名称自动申请 rtpbin 的 request pad
+    payloader
+        .link_pads(Some("src"), &rtpbin, Some("send_rtp_sink_0"))
+        .map_err(|e| OutputError::link("链接 rtpbin", e))?;
+    rtpbin
+        .link_pads(Some("send_rtp_src_0"), rtp_sink, Some("sink"))
+        .map_err(|e| OutputError::link("链接 RTP udpsink", e))?;
+    rtpbin
+        .link_pads(Some("send_rtcp_src_0"), &rtcp_sink, Some("sink"))
+        .map_err(|e| OutputError::link("链接 RTCP udpsink", e))?;
+    let rtcp_input = funnel.as_ref().unwrap_or(&rtcp_srcs[0]);
+    rtcp_input
+        .link_pads(Some("src"), &rtpbin, Some("recv_rtcp_sink_0"))
+        .map_err(|e| OutputError::link("链接 RTCP udpsrc", e))?;
+    if let Some(funnel) = &funnel {
+        for rtcp_src in &rtcp_srcs {
+            rtcp_src
+                .link(funnel)
+                .map_err(|e| OutputError::link("链接 RTCP udpsrc", e))?;
+        }
+    }
+
+    let recv_ports = rtcp_recv_ports
+        .iter()
+        .map(u16::to_string)
+        .collect::<Vec<_>>()
+        .join("、");
+    info!(
+        "RTP 会话已创建: RTCP 发送到 {}，在端口 {} 接收反馈，RTX: {}，FEC: {}",
+        rtcp_clients.join("、"),
+        recv_ports,
+        if options.rtx { "开启" } else { "关闭" },
+        fec_description(options)
+    );
+
+    Ok(rtpbin)
+}
+
+/// 输出与 RTP 会话匹配的接收端命令
+pub fn log_receiver_hint(address: &ResolvedAddress, options: &RtpOptions) {
+    let port = address.port();
+    let Ok((rtcp_send_port, rtcp_recv_port)) = rtcp_ports(port) else {
+        return;
+    };
+
+    let mut rtpbin = "rtpbin name=rtpbin latency=200".to_string();
+    if options.rtx {
+        rtpbin.push_str(" do-retransmission=true rtp-profile=avpf");
+    }
+    if options.fec_percentage > 0 {
+        rtpbin.push_str(&format!(
+            " fec-decoders='fec,0=\"rtpulpfecdec\\ pt\\={}\";'",
+            ULPFEC_PT
+        ));
+    }
+    let udpsrc_address = if address.ip().is_multicast() {
+        format!("address={} auto-multicast=true ", address.host())
+    } else {
+        String::new()
+    };
+    let red_decoder = if options.fec_percentage > 0 && options.fec_red {
+        format!("rtpreddec pt={} ! ", RED_PT)
+    } else {
+        String::new()
+    };
+
+    info!(
+        "  gst-launch-1.0 {} \
+        udpsrc {}port={} caps=\"application/x-rtp,media=video,clock-rate=90000,encoding-name=H264,payload={}\" ! {}rtpbin.recv_rtp_sink_0 \
+        rtpbin. ! rtph264depay ! avdec_h264 ! videoconvert ! autovideosink \
+        udpsrc {}port={} ! rtpbin.recv_rtcp_sink_0 \
+        rtpbin.send_rtcp_src_0 ! udpsink host=<服务端地址> port={} sync=false async=false",
+        rtpbin,
+        udpsrc_address,
+        port,
+        H264_PT,
+        red_decoder,
+        udpsrc_address,
+        rtcp_send_port,
+        rtcp_recv_port
+    );
+    if options.rtx {
+        info!(
+            "  注意: 解码 RTX（pt={}）需要接收端通过 rtpbin 的 request-aux-receiver 信号创建 rtprtxreceive，gst-launch-1.0 无法配置",
+            RTX_PT
+        );
+    }
+}
+
+/// 定期从 RTCP 接收报告中读取丢包和抖动并输出日志
+/// rtpbin 被销毁后线程自动退出
+pub fn spawn_rtcp_stats_logger(rtpbin: &gst::Element, interval: Duration) {
+    let rtpbin = rtpbin.downgrade();
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let Some(rtpbin) = rtpbin.upgrade() else {
+            break;
+        };
+        if let Some(report) = read_rtcp_report(&rtpbin) {
+            info!("RTCP 接收报告: {}", report);
+        }
+    });
+}
+
+/// 从 rtpbin 会话 0 的统计信息中读取最近一次接收报告
+/// 有多个接收端（额外的单播目标）时取各项指标中最差的，码率按最差的链路调整
+/// 还没有收到接收端的 RTCP 时返回 None
+pub fn read_rtcp_report(rtpbin: &gst::Element) -> Option<RtcpReport> {
+    let session = rtpbin.emit_by_name::<Option<glib::Object>>("get-session", &[&0u32])?;
+    let stats = session.property::<gst::Structure>("stats");
+    let sources = stats.get::<glib::ValueArray>("source-stats").ok()?;
+
+    sources
+        .iter()
+        .filter_map(|source| source.get::<gst::Structure>().ok())
+        .filter(|source| source.get::<bool>("have-rb").unwrap_or(false))
+        .map(|source| RtcpReport {
+            fraction_lost: source.get::<u32>("rb-fractionlost").unwrap_or(0) as f64 / 256.0,
+            packets_lost: source.get::<i32>("rb-packetslost").unwrap_or(0),
+            // 抖动以 RTP 时钟（90kHz）为单位
+            jitter_ms: source.get::<u32>("rb-jitter").unwrap_or(0) as f64 / 90.0,
+            // RTT 为 NTP 短格式（16.16 定点秒），报告中没有 LSR 时为 0
+            rtt_ms: source
+                .get::<u32>("rb-round-trip")
+                .ok()
+                .filter(|&rtt| rtt > 0)
+                .map(|rtt| rtt as f64 / 65536.0 * 1000.0),
+        })
+        .reduce(RtcpReport::worst)
+}
+
+/// 计算 RTCP 发送和接收端口
+fn rtcp_ports(port: u16) -> Result<(u16, u16), OutputError> {
+    match (port.checked_add(RTCP_SEND_OFFSET), port.checked_add(RTCP_RECV_OFFSET)) {
+        (Some(send), Some(recv)) => Ok((send, recv)),
+        _ => Err(OutputError::InvalidOption(format!(
+            "RTP 端口 {} 过大，RTCP 需要使用端口 {} 和 {}",
+            port,
+            port as u32 + RTCP_SEND_OFFSET as u32,
+            port as u32 + RTCP_RECV_OFFSET as u32
+        ))),
+    }
+}
+
+/// 创建 RTX 发送端（rtprtxsend），pad 名称需要与 session 编号对应
+fn make_rtx_sender(session: u32) -> Result<gst::Element, glib::BoolError> {
+    let bin = gst::Bin::new();
+    let rtxsend = gst::ElementFactory::make("rtprtxsend")
+        .property(
+            "payload-type-map",
+            gst::Structure::builder("application/x-rtp-pt-map")
+                .field("96", RTX_PT)
+                .build(),
+        )
+        .build()?;
+    bin.add(&rtxsend)?;
+
+    let src = rtxsend.static_pad("src").expect("rtprtxsend 没有 src pad");
+    let sink = rtxsend.static_pad("sink").expect("rtprtxsend 没有 sink pad");
+    bin.add_pad(
+        &gst::GhostPad::builder_with_target(&src)?
+            .name(format!("src_{}", session))
+            .build(),
+    )?;
+    bin.add_pad(
+        &gst::GhostPad::builder_with_target(&sink)?
+            .name(format!("sink_{}", session))
+            .build(),
+    )?;
+
+    Ok(bin.upcast())
+}
+
+/// 创建 FEC 编码器（rtpulpfecenc，可选 rtpredenc 封装）
+fn make_fec_encoder(percentage: u32, red: bool) -> Result<gst::Element, glib::BoolError> {
+    let fecenc = gst::ElementFactory::make("rtpulpfecenc")
+        .property("pt", ULPFEC_PT)
+        .property("percentage", percentage)
+        .build()?;
+    if !red {
+        return Ok(fecenc);
+    }
+
+    let redenc = gst::ElementFactory::make("rtpredenc")
+        .property("pt", RED_PT as i32)
+        .property("allow-no-red-blocks", true)
+        .build()?;
+
+    let bin = gst::Bin::new();
+    bin.add_many([&fecenc, &redenc])?;
+    fecenc.link(&redenc)?;
+
+    let sink = fecenc.static_pad("sink").expect("rtpulpfecenc 没有 sink pad");
+    let src = redenc.static_pad("src").expect("rtpredenc 没有 src pad");
+    bin.add_pad(&gst::GhostPad::builder_with_target(&sink)?.name("sink").build())?;
+    bin.add_pad(&gst::GhostPad::builder_with_target(&src)?.name("src").build())?;
+
+    Ok(bin.upcast())
+}
+
+fn fec_description(options: &RtpOptions) -> String {
+    match (options.fec_percentage, options.fec_red) {
+        (0, _) => "关闭".to_string(),
+        (percentage, false) => format!("ULPFEC {}%", percentage),
+        (percentage, true) => format!("ULPFEC {}%（RED 封装）", percentage),
+    }
+}
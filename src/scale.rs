@@ -0,0 +1,228 @@
+//! 输出缩放：以不同于合成器的分辨率输出画面
+//!
+//! 合成器的分辨率保持不变。GStreamer 输出在编码器前插入 videoscale ! capsfilter，
+//! VNC 输出在转换为 RGB888 之后缩放。宽高比不同时保持画面比例，用黑边填充（letterbox）。
+
+use crate::output::OutputError;
+use std::fmt;
+
+/// 缩放算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ScaleMethod {
+    /// 最近邻，速度最快
+    Nearest,
+    /// 双线性插值
+    #[default]
+    Bilinear,
+    /// 4 抽头滤波（VNC 输出使用双线性插值）
+    #[value(name = "4-tap")]
+    FourTap,
+    /// Lanczos，质量最好（VNC 输出使用双线性插值）
+    Lanczos,
+}
+
+impl ScaleMethod {
+    /// videoscale 的 method 属性值
+    fn nick(self) -> &'static str {
+        match self {
+            ScaleMethod::Nearest => "nearest-neighbour",
+            ScaleMethod::Bilinear => "bilinear",
+            ScaleMethod::FourTap => "4-tap",
+            ScaleMethod::Lanczos => "lanczos",
+        }
+    }
+}
+
+/// 缩放选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScaleOptions {
+    pub width: u32,
+    pub height: u32,
+    pub method: ScaleMethod,
+}
+
+impl ScaleOptions {
+    /// 检查输出尺寸，编码器要求宽高为偶数
+    pub fn validate(&self, even: bool) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err(format!("缩放尺寸无效: {}", self));
+        }
+        if even && (self.width % 2 != 0 || self.height % 2 != 0) {
+            return Err(format!("缩放尺寸 {} 的宽高必须为偶数", self));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ScaleOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// 解析 WIDTHxHEIGHT，用于命令行参数
+pub fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (width, height) = s
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("尺寸格式应为 WIDTHxHEIGHT，例如 1280x720: {}", s))?;
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<u32>()
+            .map_err(|e| format!("尺寸 {} 无效: {}", s, e))
+    };
+    Ok((parse(width)?, parse(height)?))
+}
+
+/// 创建 videoscale ! capsfilter
+/// add-borders 让 videoscale 在宽高比不同时添加黑边
+pub fn make_scale_elements(scale: &ScaleOptions) -> Result<Vec<gst::Element>, OutputError> {
+    let videoscale = gst::ElementFactory::make("videoscale")
+        .property("add-borders", true)
+        .property_from_str("method", scale.method.nick())
+        .build()
+        .map_err(|e| OutputError::element("videoscale", e))?;
+    let capsfilter = gst::ElementFactory::make("capsfilter")
+        .property(
+            "caps",
+            gst::Caps::builder("video/x-raw")
+                .field("width", scale.width as i32)
+                .field("height", scale.height as i32)
+                .field("pixel-aspect-ratio", gst::Fraction::new(1, 1))
+                .build(),
+        )
+        .build()
+        .map_err(|e| OutputError::element("capsfilter", e))?;
+    Ok(vec![videoscale, capsfilter])
+}
+
+/// 保持宽高比把 src 放进 dst，返回画面在 dst 中的位置 (x, y, width, height)
+pub fn letterbox(src: (u32, u32), dst: (u32, u32)) -> (u32, u32, u32, u32) {
+    let (sw, sh) = (src.0 as u64, src.1 as u64);
+    let (dw, dh) = (dst.0 as u64, dst.1 as u64);
+    let (w, h) = if sw * dh > dw * sh {
+        // 源画面更宽：上下加黑边
+        (dw, (sh * dw / sw).max(1))
+    } else {
+        // 源画面更高：左右加黑边
+        ((sw * dh / sh).max(1), dh)
+    };
+    (
+        ((dw - w) / 2) as u32,
+        ((dh - h) / 2) as u32,
+        w as u32,
+        h as u32,
+    )
+}
+
+/// 缩放 RGB888 画面（行之间没有填充），空白区域为黑色
+pub fn scale_rgb(src: &[u8], src_width: u32, src_height: u32, scale: &ScaleOptions) -> Vec<u8> {
+    let (dw, dh) = (scale.width as usize, scale.height as usize);
+    let (sw, sh) = (src_width as usize, src_height as usize);
+    let mut dst = vec![0u8; dw * dh * 3];
+    let (x0, y0, w, h) = letterbox((src_width, src_height), (scale.width, scale.height));
+    let (x0, y0, w, h) = (x0 as usize, y0 as usize, w as usize, h as usize);
+
+    for y in 0..h {
+        let row = &mut dst[((y0 + y) * dw + x0) * 3..((y0 + y) * dw + x0 + w) * 3];
+        for (x, out) in row.chunks_exact_mut(3).enumerate() {
+            match scale.method {
+                ScaleMethod::Nearest => {
+                    let i = ((y * sh / h) * sw + x * sw / w) * 3;
+                    out.copy_from_slice(&src[i..i + 3]);
+                }
+                // 4-tap 和 lanczos 在 VNC 输出中使用双线性插值
+                _ => {
+                    // 采样点对齐像素中心
+                    let fx = (x as f32 + 0.5) * sw as f32 / w as f32 - 0.5;
+                    let fy = (y as f32 + 0.5) * sh as f32 / h as f32 - 0.5;
+                    out.copy_from_slice(&bilinear(src, sw, sh, fx, fy));
+                }
+            }
+        }
+    }
+    dst
+}
+
+/// 在 (fx, fy) 处对 RGB888 画面做双线性插值
+fn bilinear(src: &[u8], width: usize, height: usize, fx: f32, fy: f32) -> [u8; 3] {
+    let fx = fx.clamp(0.0, (width - 1) as f32);
+    let fy = fy.clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (fx as usize, fy as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (ax, ay) = (fx - x0 as f32, fy - y0 as f32);
+
+    let at = |x: usize, y: usize, c: usize| src[(y * width + x) * 3 + c] as f32;
+    [0, 1, 2].map(|c| {
+        let top = at(x0, y0, c) * (1.0 - ax) + at(x1, y0, c) * ax;
+        let bottom = at(x0, y1, c) * (1.0 - ax) + at(x1, y1, c) * ax;
+        (top * (1.0 - ay) + bottom * ay).round() as u8
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letterbox_keeps_same_aspect_ratio() {
+        assert_eq!(letterbox((2560, 1440), (1280, 720)), (0, 0, 1280, 720));
+        assert_eq!(letterbox((640, 480), (1280, 960)), (0, 0, 1280, 960));
+    }
+
+    #[test]
+    fn letterbox_adds_bars_above_and_below_wider_source() {
+        // 16:9 放进 4:3
+        assert_eq!(letterbox((1920, 1080), (640, 480)), (0, 60, 640, 360));
+    }
+
+    #[test]
+    fn letterbox_adds_bars_left_and_right_for_taller_source() {
+        // 4:3 放进 16:9
+        assert_eq!(letterbox((640, 480), (1280, 720)), (160, 0, 960, 720));
+        // 竖屏
+        assert_eq!(letterbox((1080, 1920), (1920, 1080)), (656, 0, 607, 1080));
+    }
+
+    #[test]
+    fn letterbox_never_returns_empty_picture() {
+        assert_eq!(letterbox((10000, 1), (100, 100)), (0, 49, 100, 1));
+        assert_eq!(letterbox((1, 10000), (100, 100)), (49, 0, 1, 100));
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1280x720"), Ok((1280, 720)));
+        assert_eq!(parse_size("640X480"), Ok((640, 480)));
+        assert_eq!(parse_size(" 800 x 600 "), Ok((800, 600)));
+        assert!(parse_size("1280").is_err());
+        assert!(parse_size("1280x").is_err());
+        assert!(parse_size("-1x720").is_err());
+    }
+
+    #[test]
+    fn validates_sizes() {
+        let scale = |width, height| ScaleOptions {
+            width,
+            height,
+            method: ScaleMethod::Bilinear,
+        };
+        assert!(scale(1280, 720).validate(true).is_ok());
+        assert!(scale(0, 720).validate(false).is_err());
+        assert!(scale(1279, 720).validate(false).is_ok());
+        assert!(scale(1279, 720).validate(true).is_err());
+    }
+
+    #[test]
+    fn scale_rgb_fills_bars_with_black() {
+        // 2x1 的白色画面放进 2x2，上下各有半行黑边，取整后画面在第 0 行
+        let src = [255u8; 2 * 3];
+        let scale = ScaleOptions {
+            width: 2,
+            height: 2,
+            method: ScaleMethod::Nearest,
+        };
+        let dst = scale_rgb(&src, 2, 1, &scale);
+        assert_eq!(&dst[..6], &[255; 6]);
+        assert_eq!(&dst[6..], &[0; 6]);
+    }
+}
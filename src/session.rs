@@ -0,0 +1,876 @@
+//! 合成器会话：创建 WaylandDisplay（或测试图案）、启动输出，并在当前线程中驱动取帧循环
+//!
+//! 注意：frame() 必须在创建 WaylandDisplay 的线程中调用，所以 Session 的所有方法都应该在
+//! 调用 build() 的线程中使用。
+
+use crate::bus::Failure;
+use crate::change::{ChangeGate, KeyframeTimer};
+use crate::clients::{ClientTracker, Demand, OnDemand};
+use crate::crop::CropRect;
+use crate::error::{AppError, DisplayError};
+use crate::format;
+use crate::input::{self, InputEvent, InputOptions};
+use crate::latency;
+use crate::output::{self, AppSrcOptions, AppSrcOutput, OutputError};
+use crate::pacing::FramePacer;
+use crate::source::{FrameSource, SourceKind, TestPattern, TestPatternSource};
+use crate::vnc::{self, VncOptions, VncOutput};
+use gst::prelude::*;
+use gst_video::VideoInfo;
+use std::ffi::OsStr;
+use std::process::Command;
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, warn};
+use wayland_display_core::{GstVideoInfo, WaylandDisplay};
+
+/// 等待 compositor 线程初始化完成的时间
+const STARTUP_DELAY: Duration = Duration::from_millis(100);
+
+/// 没有启用输出时检查重新加载请求的间隔
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 取帧或发送帧连续失败这么多次后停止运行，偶发的失败只输出日志
+pub(crate) const MAX_CONSECUTIVE_FAILURES: u32 = 30;
+
+/// 输出方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputConfig {
+    /// 编码为 H.264 后通过 UDP、TCP 或 SRT 发送
+    AppSrc(AppSrcOptions),
+    /// 内置 VNC 服务器
+    Vnc(VncOptions),
+}
+
+/// 取帧循环的选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FrameOptions {
+    variable_fps: bool,
+    idle_skip: bool,
+    on_demand: bool,
+    keepalive_interval: Duration,
+    keyframe_interval: Duration,
+}
+
+/// 会话构建器，默认值与命令行参数的默认值一致
+#[derive(Debug, Clone)]
+pub struct SessionBuilder {
+    render_node: String,
+    width: u32,
+    height: u32,
+    fps: gst::Fraction,
+    format: gst_video::VideoFormat,
+    source: SourceKind,
+    test_pattern: TestPattern,
+    frames: FrameOptions,
+    output: Option<OutputConfig>,
+    input: Option<InputOptions>,
+}
+
+impl Default for SessionBuilder {
+    fn default() -> SessionBuilder {
+        SessionBuilder {
+            render_node: "software".to_string(),
+            width: 1920,
+            height: 1080,
+            fps: gst::Fraction::new(60, 1),
+            format: gst_video::VideoFormat::Rgbx,
+            source: SourceKind::Compositor,
+            test_pattern: TestPattern::Smpte,
+            frames: FrameOptions {
+                variable_fps: false,
+                idle_skip: false,
+                on_demand: false,
+                keepalive_interval: Duration::from_millis(1000),
+                keyframe_interval: Duration::from_secs(2),
+            },
+            output: None,
+            input: None,
+        }
+    }
+}
+
+impl SessionBuilder {
+    /// 渲染节点路径（例如 /dev/dri/renderD128），"software" 表示软件渲染
+    pub fn render_node(mut self, render_node: impl Into<String>) -> SessionBuilder {
+        self.render_node = render_node.into();
+        self
+    }
+
+    /// 输出分辨率
+    pub fn size(mut self, width: u32, height: u32) -> SessionBuilder {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// 帧率，可变帧率模式下为取帧的最高帧率
+    pub fn fps(mut self, fps: gst::Fraction) -> SessionBuilder {
+        self.fps = fps;
+        self
+    }
+
+    /// 视频格式；合成器只输出 RGBx、RGBA、BGRx 和 BGRA，其他格式由 appsrc 输出在编码前转换，
+    /// video_info() 和 frame() 使用合成器实际输出的格式
+    pub fn format(mut self, format: gst_video::VideoFormat) -> SessionBuilder {
+        self.format = format;
+        self
+    }
+
+    /// 帧来源，使用测试图案时不启动合成器
+    pub fn source(mut self, source: SourceKind) -> SessionBuilder {
+        self.source = source;
+        self
+    }
+
+    /// 测试图案，source 为 TestPattern 时使用
+    pub fn test_pattern(mut self, pattern: TestPattern) -> SessionBuilder {
+        self.test_pattern = pattern;
+        self
+    }
+
+    /// 可变帧率：只在画面变化时推送帧
+    pub fn variable_fps(mut self, enabled: bool) -> SessionBuilder {
+        self.frames.variable_fps = enabled;
+        self
+    }
+
+    /// 保持恒定帧率，但画面未变化时跳过编码和发送
+    pub fn idle_skip(mut self, enabled: bool) -> SessionBuilder {
+        self.frames.idle_skip = enabled;
+        self
+    }
+
+    /// 没有客户端连接时暂停取帧和编码
+    pub fn on_demand(mut self, enabled: bool) -> SessionBuilder {
+        self.frames.on_demand = enabled;
+        self
+    }
+
+    /// 画面未变化时两帧之间的最长间隔
+    pub fn keepalive_interval(mut self, interval: Duration) -> SessionBuilder {
+        self.frames.keepalive_interval = interval;
+        self
+    }
+
+    /// 跳过画面时强制关键帧的间隔
+    pub fn keyframe_interval(mut self, interval: Duration) -> SessionBuilder {
+        self.frames.keyframe_interval = interval;
+        self
+    }
+
+    /// 输出方式，不设置时只运行合成器，可以通过 Session::frame() 自行取帧
+    pub fn output(mut self, output: OutputConfig) -> SessionBuilder {
+        self.output = Some(output);
+        self
+    }
+
+    /// 监听 viewer 发回的键盘和鼠标事件并注入合成器，见 input 模块
+    pub fn input(mut self, options: InputOptions) -> SessionBuilder {
+        self.input = Some(options);
+        self
+    }
+
+    /// 去掉输出配置，用于只通过 frame() 取帧的场合
+    pub(crate) fn without_output(mut self) -> SessionBuilder {
+        self.output = None;
+        self
+    }
+
+    /// 创建合成器并启动输出
+    pub fn build(self) -> Result<Session, AppError> {
+        gst::init().map_err(AppError::Init)?;
+        let config = self.clone();
+
+        // 合成器只输出 32 位 RGB 格式，其他格式由 appsrc 输出在编码前转换；测试图案直接生成指定的格式
+        let source_format = match self.source {
+            SourceKind::Compositor => format::compositor_format(self.format),
+            SourceKind::TestPattern => self.format,
+        };
+        if source_format != self.format {
+            info!(
+                "合成器输出 {}，在编码前转换为 {}",
+                source_format, self.format
+            );
+        }
+        let video_info = VideoInfo::builder(source_format, self.width, self.height)
+            .fps(self.fps)
+            .build()
+            .map_err(|e| {
+                AppError::Config(format!(
+                    "无效的视频参数 {}x{} @ {} fps ({:?}): {}",
+                    self.width, self.height, self.fps, self.format, e
+                ))
+            })?;
+
+        let (source, env_vars) = match self.source {
+            SourceKind::Compositor => {
+                let mut display = WaylandDisplay::new(Some(self.render_node.clone()))
+                    .map_err(|e| AppError::Display(DisplayError::Create(format!("{:?}", e))))?;
+
+                // 等待 compositor 线程初始化完成
+                std::thread::sleep(STARTUP_DELAY);
+
+                let env_vars: Vec<String> = display.env_vars().map(|s| s.to_string()).collect();
+
+                // 设置视频信息（这会创建输出）
+                display.set_video_info(GstVideoInfo::RAW(video_info.clone()));
+                (FrameSource::Compositor(display), env_vars)
+            }
+            SourceKind::TestPattern => {
+                info!("使用测试图案 {}，不启动合成器", self.test_pattern);
+                let source = TestPatternSource::new(self.test_pattern, &video_info)
+                    .map_err(|e| AppError::Display(DisplayError::TestPattern(e.to_string())))?;
+                (FrameSource::TestPattern(source), Vec::new())
+            }
+        };
+
+        // 客户端计数，用于按需取帧
+        let clients = ClientTracker::new();
+        let mut on_demand = false;
+
+        let output = match self.output {
+            Some(OutputConfig::AppSrc(mut options)) => {
+                info!(
+                    "使用 appsrc 方式暴露输出流到 {}: {}",
+                    options.protocol.to_string().to_uppercase(),
+                    options.output_address
+                );
+                options.variable_framerate = self.frames.variable_fps;
+                options.format = Some(self.format);
+                if self.frames.on_demand {
+                    if options.tracks_clients() {
+                        on_demand = true;
+                    } else {
+                        warn!(
+                            "{} 输出无法得知接收端是否在线，--on-demand 只支持 tcp 和 listener 模式的 srt，将持续取帧",
+                            options.protocol
+                        );
+                    }
+                }
+                let output = AppSrcOutput::start(video_info.clone(), options, clients.clone())?;
+                Some(Output::AppSrc(output))
+            }
+            Some(OutputConfig::Vnc(options)) => {
+                info!("使用 VNC 服务器暴露输出流，端口: {}", options.port);
+                on_demand = self.frames.on_demand;
+                let output = vnc::start_vnc_output(video_info.clone(), &options, clients.clone())?;
+                Some(Output::Vnc(output))
+            }
+            None => None,
+        };
+
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let (reload_tx, reload_rx) = mpsc::channel();
+
+        // 输入事件和重新加载请求走同一个通道，由取帧循环在合成器线程中注入
+        match (&self.input, &source) {
+            (Some(options), FrameSource::Compositor(_)) => {
+                let tx = reload_tx.clone();
+                input::spawn_listener(options, move |event| {
+                    let _ = tx.send(Control::Input(event));
+                })?;
+            }
+            (Some(_), FrameSource::TestPattern(_)) => {
+                warn!("测试图案没有合成器，不启动输入通道");
+            }
+            (None, _) => {}
+        }
+        let reload = Reload {
+            rx: reload_rx,
+            current: config,
+            video_info: video_info.clone(),
+            clients: clients.clone(),
+        };
+        Ok(Session {
+            source,
+            video_info,
+            env_vars,
+            output,
+            clients,
+            on_demand,
+            frames: self.frames,
+            stop_tx,
+            stop_rx,
+            reload_tx,
+            reload,
+        })
+    }
+}
+
+/// 已启动的输出
+enum Output {
+    AppSrc(AppSrcOutput),
+    Vnc(VncOutput),
+}
+
+/// 用于从其他线程（例如信号处理器）停止 Session::run()
+#[derive(Debug, Clone)]
+pub struct StopHandle {
+    tx: mpsc::Sender<()>,
+}
+
+impl StopHandle {
+    pub fn stop(&self) {
+        let _ = self.tx.send(());
+    }
+}
+
+/// 用于从其他线程（例如 SIGHUP 处理器）修改 Session::run() 的配置
+///
+/// 输出选项（包括 VNC 的监听、密码和缩放设置）会在运行中生效；分辨率、帧率、格式、渲染节点、
+/// 取帧选项以及输出方式的切换需要重启，只会在日志中列出
+#[derive(Debug, Clone)]
+pub struct ReloadHandle {
+    tx: mpsc::Sender<Control>,
+}
+
+impl ReloadHandle {
+    /// 换用新的配置
+    pub fn reload(&self, builder: SessionBuilder) {
+        let _ = self.tx.send(Control::Reload(builder));
+    }
+
+    /// 修改当前输出的裁剪区域，None 表示输出整个画面
+    pub fn crop(&self, crop: Option<CropRect>) {
+        let _ = self.tx.send(Control::Crop(crop));
+    }
+}
+
+/// 转发给 Session::run() 的请求
+#[derive(Debug)]
+enum Control {
+    Reload(SessionBuilder),
+    Crop(Option<CropRect>),
+    Input(InputEvent),
+}
+
+/// Reload::poll 可以修改的输出
+enum OutputMut<'a> {
+    None,
+    AppSrc(&'a mut AppSrcOutput),
+    Vnc(&'a mut VncOutput),
+}
+
+/// 等待应用的请求，以及当前生效的配置
+struct Reload {
+    rx: mpsc::Receiver<Control>,
+    current: SessionBuilder,
+    video_info: VideoInfo,
+    /// 重启 VNC 服务器时交给新的服务器
+    clients: ClientTracker,
+}
+
+impl Reload {
+    /// 应用收到的请求
+    fn poll(&mut self, source: &FrameSource, mut output: OutputMut<'_>) {
+        while let Ok(control) = self.rx.try_recv() {
+            match control {
+                Control::Reload(next) => self.apply(next, &mut output),
+                Control::Crop(crop) => self.crop(crop, &mut output),
+                Control::Input(event) => self.input(event, source),
+            }
+        }
+    }
+
+    /// 注入输入事件，指针坐标按当前输出的裁剪和缩放换算
+    fn input(&self, event: InputEvent, source: &FrameSource) {
+        let Some(display) = source.display() else {
+            return;
+        };
+        match event {
+            InputEvent::Key { key, pressed } => display.keyboard_input(key, pressed),
+            InputEvent::Motion { x, y } => {
+                let (crop, scale) = match &self.current.output {
+                    Some(OutputConfig::AppSrc(options)) => (options.crop, options.scale),
+                    Some(OutputConfig::Vnc(options)) => (options.crop, options.scale),
+                    None => (None, None),
+                };
+                let (x, y) = input::map_pointer(
+                    x,
+                    y,
+                    (self.video_info.width(), self.video_info.height()),
+                    crop.as_ref(),
+                    scale.map(|scale| (scale.width, scale.height)),
+                );
+                display.pointer_motion_absolute(x, y);
+            }
+            InputEvent::Button { button, pressed } => display.pointer_button(button, pressed),
+            InputEvent::Axis { dx, dy } => display.pointer_axis(dx, dy),
+        }
+    }
+
+    fn crop(&mut self, crop: Option<CropRect>, output: &mut OutputMut<'_>) {
+        let result = match (output, &self.current.output) {
+            (OutputMut::AppSrc(output), Some(OutputConfig::AppSrc(options))) => {
+                let mut new = options.clone();
+                new.variable_framerate = self.current.frames.variable_fps;
+                new.format = Some(self.current.format);
+                new.crop = crop;
+                output.reconfigure(new).map_err(AppError::from)
+            }
+            (OutputMut::Vnc(output), Some(OutputConfig::Vnc(_))) => output
+                .set_crop(crop, &self.video_info)
+                .map_err(AppError::from),
+            _ => {
+                warn!("当前没有启用输出，忽略裁剪区域");
+                return;
+            }
+        };
+        match result {
+            Ok(()) => match &mut self.current.output {
+                Some(OutputConfig::AppSrc(options)) => options.crop = crop,
+                Some(OutputConfig::Vnc(options)) => options.crop = crop,
+                None => {}
+            },
+            Err(e) => error!("无法修改裁剪区域: {}", e),
+        }
+    }
+
+    fn apply(&mut self, next: SessionBuilder, output: &mut OutputMut<'_>) {
+        info!("正在重新加载配置");
+        let current = &self.current;
+        let mut restart = Vec::new();
+        if next.render_node != current.render_node {
+            restart.push("render-node");
+        }
+        if (next.width, next.height) != (current.width, current.height) {
+            restart.push("width/height");
+        }
+        if next.fps != current.fps {
+            restart.push("fps");
+        }
+        if next.format != current.format {
+            restart.push("format");
+        }
+        if (next.source, next.test_pattern) != (current.source, current.test_pattern) {
+            restart.push("source/test-pattern");
+        }
+        if next.input != current.input {
+            restart.push("input-listen/input-protocol");
+        }
+        if next.frames != current.frames {
+            restart.push("variable-fps/idle-skip/on-demand/keepalive-interval/keyframe-interval");
+        }
+
+        match (&current.output, &next.output, output) {
+            (old, new, _) if output_kind(old) != output_kind(new) => restart.push("output"),
+            (
+                Some(OutputConfig::AppSrc(old)),
+                Some(OutputConfig::AppSrc(new)),
+                OutputMut::AppSrc(output),
+            ) => {
+                let mut new = new.clone();
+                // 可变帧率属于取帧选项，重启前保持不变
+                new.variable_framerate = current.frames.variable_fps;
+                new.format = Some(current.format);
+                if current.frames.on_demand && new.tracks_clients() != old.tracks_clients() {
+                    restart.push("protocol/srt-mode（与 --on-demand 一起使用时）");
+                } else if let Err(e) = output.reconfigure(new) {
+                    error!("新的输出选项无效，保持原有设置: {}", e);
+                } else {
+                    self.current.output = next.output.clone();
+                }
+            }
+            (
+                Some(OutputConfig::Vnc(old)),
+                Some(OutputConfig::Vnc(new)),
+                OutputMut::Vnc(output),
+            ) => {
+                // 监听地址、密码和帧缓冲区尺寸在创建服务器时确定，变化时重启 VNC 服务器，
+                // 只修改裁剪区域时不需要断开客户端
+                let others_changed = VncOptions {
+                    crop: old.crop,
+                    ..new.clone()
+                } != *old;
+                if others_changed {
+                    // 端口可能不变，先停止原来的服务器再按新设置启动
+                    output.stop();
+                    let started =
+                        vnc::start_vnc_output(self.video_info.clone(), new, self.clients.clone());
+                    match started {
+                        Ok(restarted) => {
+                            **output = restarted;
+                            self.current.output = next.output.clone();
+                            info!("VNC 服务器已按新设置重启，客户端需要重新连接");
+                        }
+                        Err(e) => {
+                            error!("无法按新设置启动 VNC 服务器，恢复原有设置: {}", e);
+                            match vnc::start_vnc_output(
+                                self.video_info.clone(),
+                                old,
+                                self.clients.clone(),
+                            ) {
+                                Ok(restored) => **output = restored,
+                                Err(e) => error!("无法恢复 VNC 服务器: {}", e),
+                            }
+                        }
+                    }
+                } else if new.crop != old.crop {
+                    match output.set_crop(new.crop, &self.video_info) {
+                        Ok(()) => {
+                            if let Some(OutputConfig::Vnc(options)) = &mut self.current.output {
+                                options.crop = new.crop;
+                            }
+                        }
+                        Err(e) => error!("新的裁剪区域无效，保持原有设置: {}", e),
+                    }
+                }
+            }
+            (None, None, _) => {}
+            // 配置与正在运行的输出不一致，无法在运行中修改
+            _ => restart.push("output"),
+        }
+
+        if restart.is_empty() {
+            info!("配置已重新加载");
+        } else {
+            warn!("以下设置需要重启才能生效: {}", restart.join(", "));
+        }
+    }
+}
+
+fn output_kind(output: &Option<OutputConfig>) -> &'static str {
+    match output {
+        Some(OutputConfig::AppSrc(_)) => "appsrc",
+        Some(OutputConfig::Vnc(_)) => "vnc",
+        None => "none",
+    }
+}
+
+/// 正在运行的合成器会话
+pub struct Session {
+    source: FrameSource,
+    video_info: VideoInfo,
+    env_vars: Vec<String>,
+    output: Option<Output>,
+    clients: ClientTracker,
+    on_demand: bool,
+    frames: FrameOptions,
+    stop_tx: mpsc::Sender<()>,
+    stop_rx: mpsc::Receiver<()>,
+    reload_tx: mpsc::Sender<Control>,
+    reload: Reload,
+}
+
+impl Session {
+    pub fn builder() -> SessionBuilder {
+        SessionBuilder::default()
+    }
+
+    /// Wayland 客户端需要的环境变量，格式为 KEY=VALUE；使用测试图案时为空
+    pub fn env_vars(&self) -> &[String] {
+        &self.env_vars
+    }
+
+    /// Wayland socket 名称（WAYLAND_DISPLAY 的值）
+    pub fn wayland_display(&self) -> Option<&str> {
+        self.env_vars
+            .iter()
+            .find_map(|var| var.strip_prefix("WAYLAND_DISPLAY="))
+    }
+
+    /// 创建一个连接到本合成器的子进程命令（已设置好环境变量）
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let mut command = Command::new(program);
+        for var in &self.env_vars {
+            if let Some((key, value)) = var.split_once('=') {
+                command.env(key, value);
+            }
+        }
+        command
+    }
+
+    pub fn video_info(&self) -> &VideoInfo {
+        &self.video_info
+    }
+
+    /// 当前连接的客户端数量
+    pub fn client_count(&self) -> usize {
+        self.clients.count()
+    }
+
+    /// 获取当前画面
+    /// 启用了输出时由 run() 负责取帧，不需要再调用
+    pub fn frame(&mut self) -> Result<gst::Buffer, DisplayError> {
+        self.source.frame().map_err(DisplayError::from)
+    }
+
+    /// 键盘输入，key 为 Linux evdev 键码（例如 KEY_A 为 30）
+    /// 这一组输入方法在使用测试图案时不起作用
+    pub fn keyboard_input(&self, key: u32, pressed: bool) {
+        if let Some(display) = self.source.display() {
+            display.keyboard_input(key, pressed);
+        }
+    }
+
+    /// 相对指针移动
+    pub fn pointer_motion(&self, dx: f64, dy: f64) {
+        if let Some(display) = self.source.display() {
+            display.pointer_motion(dx, dy);
+        }
+    }
+
+    /// 绝对指针位置（像素）
+    pub fn pointer_motion_absolute(&self, x: f64, y: f64) {
+        if let Some(display) = self.source.display() {
+            display.pointer_motion_absolute(x, y);
+        }
+    }
+
+    /// 鼠标按键，button 为 Linux evdev 按键码（例如 BTN_LEFT 为 0x110）
+    pub fn pointer_button(&self, button: u32, pressed: bool) {
+        if let Some(display) = self.source.display() {
+            display.pointer_button(button, pressed);
+        }
+    }
+
+    /// 滚轮
+    pub fn pointer_axis(&self, x: f64, y: f64) {
+        if let Some(display) = self.source.display() {
+            display.pointer_axis(x, y);
+        }
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle {
+            tx: self.stop_tx.clone(),
+        }
+    }
+
+    pub fn reload_handle(&self) -> ReloadHandle {
+        ReloadHandle {
+            tx: self.reload_tx.clone(),
+        }
+    }
+
+    /// 按帧率取帧并发送到输出，直到收到停止信号或合成器关闭
+    /// 没有启用输出时只等待停止信号
+    /// 取帧或发送帧连续失败时返回错误，合成器关闭（flushing、EOS）不算错误
+    pub fn run(&mut self) -> Result<(), AppError> {
+        let demand = self.on_demand.then(|| OnDemand::new(self.clients.clone()));
+        match &mut self.output {
+            Some(Output::AppSrc(output)) => run_appsrc(
+                &mut self.source,
+                output,
+                &self.video_info,
+                &self.frames,
+                demand,
+                &self.stop_rx,
+                &mut self.reload,
+            ),
+            Some(Output::Vnc(output)) => run_vnc(
+                &mut self.source,
+                output,
+                &self.video_info,
+                &self.frames,
+                demand,
+                &self.stop_rx,
+                &mut self.reload,
+            ),
+            None => {
+                while let Err(mpsc::RecvTimeoutError::Timeout) =
+                    self.stop_rx.recv_timeout(RELOAD_POLL_INTERVAL)
+                {
+                    self.reload.poll(&self.source, OutputMut::None);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// 停止输出（appsrc 会先发送 EOS）并关闭合成器
+    pub fn shutdown(mut self) {
+        info!("正在清理资源...");
+        if let Some(Output::AppSrc(output)) = self.output.take() {
+            output.shutdown();
+        }
+        // display 或测试图案会在 drop 时自动清理
+    }
+}
+
+/// appsrc 输出的取帧循环，按截止时间控制帧率，见 pacing 模块
+fn run_appsrc(
+    source: &mut FrameSource,
+    appsrc_output: &mut AppSrcOutput,
+    video_info: &VideoInfo,
+    frames: &FrameOptions,
+    mut demand: Option<OnDemand>,
+    stop_rx: &mpsc::Receiver<()>,
+    reload: &mut Reload,
+) -> Result<(), AppError> {
+    // 可变帧率或 --idle-skip 模式下按最高帧率取帧，只推送画面变化的帧
+    // 被跳过的帧不会进入编码器，需要定期强制关键帧
+    let mut pacer = FramePacer::new(video_info.fps(), "appsrc");
+    let mut gate = (frames.variable_fps || frames.idle_skip)
+        .then(|| ChangeGate::new(frames.keepalive_interval));
+    let mut keyframes = gate
+        .is_some()
+        .then(|| KeyframeTimer::new(frames.keyframe_interval));
+    // 连续失败次数，成功一次后清零
+    let (mut frame_failures, mut push_failures) = (0u32, 0u32);
+
+    while pacer.wait(stop_rx) {
+        reload.poll(source, OutputMut::AppSrc(&mut *appsrc_output));
+
+        // 处理总线消息；pipeline 出错后在重建完成前不取帧
+        if appsrc_output.poll() {
+            if let Some(gate) = &mut gate {
+                gate.reset();
+            }
+        }
+        let Some(appsrc) = appsrc_output.appsrc().cloned() else {
+            continue;
+        };
+
+        // 没有客户端时不取帧；有客户端连接时立即发送关键帧
+        match demand.as_mut().map(OnDemand::poll) {
+            Some(Demand::Idle) => continue,
+            Some(Demand::Resumed) => {
+                if let Some(gate) = &mut gate {
+                    gate.reset();
+                }
+                output::request_keyframe(appsrc.upcast_ref());
+            }
+            Some(Demand::Active) | None => {}
+        }
+
+        // 获取帧并推送
+        match source.frame() {
+            Ok(mut buffer) => {
+                frame_failures = 0;
+                // 延迟条码记录取到帧的时间，不包括后面的比较和推送
+                let captured = SystemTime::now();
+                if let Some(gate) = &mut gate {
+                    if !gate.should_send(&buffer) {
+                        pacer.frame_skipped();
+                        continue;
+                    }
+                }
+                if keyframes.as_mut().is_some_and(|timer| timer.due()) {
+                    output::request_keyframe(appsrc.upcast_ref());
+                }
+
+                // PTS 取自 pipeline 时钟，下游的 muxer 和 RTP 时间戳依赖它
+                let buffer_ref = buffer.make_mut();
+                if appsrc_output.options().latency_stamp {
+                    if let Err(e) = latency::stamp(buffer_ref, video_info, captured) {
+                        warn!("无法画出延迟条码: {}", e);
+                    }
+                }
+                pacer.stamp(buffer_ref, appsrc.current_running_time());
+                if frames.variable_fps {
+                    // 可变帧率下这一帧持续到下一帧出现为止，时长未知
+                    buffer_ref.set_duration(gst::ClockTime::NONE);
+                }
+                match appsrc.push_buffer(buffer) {
+                    Ok(_) => {
+                        push_failures = 0;
+                        pacer.frame_sent();
+                    }
+                    // pipeline 正在重建或关闭，由 poll() 处理
+                    Err(gst::FlowError::Flushing) => {}
+                    Err(e) => {
+                        error!("推送 buffer 失败: {:?}", e);
+                        push_failures += 1;
+                        if push_failures >= MAX_CONSECUTIVE_FAILURES {
+                            return Err(AppError::Output(OutputError::Pipeline(Failure {
+                                source: "appsrc".to_string(),
+                                message: format!(
+                                    "连续 {} 次推送 buffer 失败: {:?}",
+                                    push_failures, e
+                                ),
+                                from_encoder: false,
+                            })));
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                let e = DisplayError::from(e);
+                if e.is_shutdown() {
+                    info!("Pipeline 正在关闭: {}", e);
+                    break;
+                }
+                frame_failures += 1;
+                if frame_failures >= MAX_CONSECUTIVE_FAILURES {
+                    return Err(AppError::Display(e));
+                }
+                warn!("{}，继续尝试...", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// VNC 输出的取帧循环
+fn run_vnc(
+    source: &mut FrameSource,
+    vnc_output: &mut VncOutput,
+    video_info: &VideoInfo,
+    frames: &FrameOptions,
+    mut demand: Option<OnDemand>,
+    stop_rx: &mpsc::Receiver<()>,
+    reload: &mut Reload,
+) -> Result<(), AppError> {
+    let mut pacer = FramePacer::new(video_info.fps(), "VNC");
+    let mut gate = frames
+        .idle_skip
+        .then(|| ChangeGate::new(frames.keepalive_interval));
+    // 连续失败次数，成功一次后清零
+    let (mut frame_failures, mut send_failures) = (0u32, 0u32);
+
+    while pacer.wait(stop_rx) {
+        reload.poll(source, OutputMut::Vnc(&mut *vnc_output));
+
+        // 没有 VNC 客户端时不取帧
+        match demand.as_mut().map(OnDemand::poll) {
+            Some(Demand::Idle) => continue,
+            Some(Demand::Resumed) => {
+                if let Some(gate) = &mut gate {
+                    gate.reset();
+                }
+            }
+            Some(Demand::Active) | None => {}
+        }
+
+        // 获取帧并发送到 VNC 服务器
+        match source.frame() {
+            Ok(buffer) => {
+                frame_failures = 0;
+                if let Some(gate) = &mut gate {
+                    if !gate.should_send(&buffer) {
+                        pacer.frame_skipped();
+                        continue;
+                    }
+                }
+                match vnc_output.send_frame(&buffer, video_info) {
+                    Ok(()) => {
+                        send_failures = 0;
+                        pacer.frame_sent();
+                    }
+                    Err(e) => {
+                        error!("发送帧到 VNC 服务器失败: {}", e);
+                        send_failures += 1;
+                        if send_failures >= MAX_CONSECUTIVE_FAILURES {
+                            return Err(AppError::Vnc(e));
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                let e = DisplayError::from(e);
+                if e.is_shutdown() {
+                    info!("正在关闭: {}", e);
+                    break;
+                }
+                frame_failures += 1;
+                if frame_failures >= MAX_CONSECUTIVE_FAILURES {
+                    return Err(AppError::Display(e));
+                }
+                warn!("{}，继续尝试...", e);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+//! 帧来源：合成器画面，或者 videotestsrc 生成的测试图案
+//!
+//! 测试图案经过与合成器画面完全相同的取帧循环、编码器和传输，不需要启动合成器和 Wayland 应用，
+//! 用于排查网络和接收端的问题。
+
+use gst::prelude::*;
+use gst_app::AppSink;
+use gst_video::VideoInfo;
+use std::fmt;
+use wayland_display_core::WaylandDisplay;
+
+/// 帧来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SourceKind {
+    /// Wayland 合成器的画面
+    #[default]
+    Compositor,
+    /// videotestsrc 测试图案，不启动合成器
+    #[value(name = "testpattern")]
+    TestPattern,
+}
+
+/// 测试图案
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TestPattern {
+    /// SMPTE 彩条，向左滚动
+    #[default]
+    Smpte,
+    /// 移动的小球
+    Ball,
+    /// 随机噪点，用于测试高码率
+    Snow,
+    /// 彩色渐变，向左滚动
+    Colors,
+    /// 8x8 棋盘格，向左滚动，用于检查缩放和色度抽样
+    Checkers,
+    /// 波带片，向左滚动，用于检查编码质量
+    ZonePlate,
+}
+
+impl TestPattern {
+    /// videotestsrc 的 pattern 属性值
+    fn nick(self) -> &'static str {
+        match self {
+            TestPattern::Smpte => "smpte",
+            TestPattern::Ball => "ball",
+            TestPattern::Snow => "snow",
+            TestPattern::Colors => "colors",
+            TestPattern::Checkers => "checkers-8",
+            TestPattern::ZonePlate => "zone-plate",
+        }
+    }
+
+    /// 静止的图案需要滚动，接收端才能看出画面是否在更新
+    fn scrolls(self) -> bool {
+        !matches!(self, TestPattern::Ball | TestPattern::Snow)
+    }
+}
+
+impl fmt::Display for TestPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.nick())
+    }
+}
+
+/// 每帧滚动的像素数
+const SCROLL_SPEED: i32 = 4;
+
+/// videotestsrc ! capsfilter ! appsink，每次 frame() 取出一帧
+///
+/// videotestsrc 不是实时源，由取帧循环按帧率拉取，图案按帧序号移动
+pub(crate) struct TestPatternSource {
+    pipeline: gst::Pipeline,
+    appsink: AppSink,
+}
+
+impl TestPatternSource {
+    pub(crate) fn new(
+        pattern: TestPattern,
+        video_info: &VideoInfo,
+    ) -> Result<TestPatternSource, gst::glib::BoolError> {
+        let mut builder =
+            gst::ElementFactory::make("videotestsrc").property_from_str("pattern", pattern.nick());
+        if pattern.scrolls() {
+            builder = builder.property("horizontal-speed", SCROLL_SPEED);
+        }
+        let videotestsrc = builder.build()?;
+        let appsink = AppSink::builder()
+            .caps(&video_info.to_caps()?)
+            .sync(false)
+            .max_buffers(1)
+            .build();
+
+        let pipeline = gst::Pipeline::new();
+        pipeline.add_many([&videotestsrc, appsink.upcast_ref()])?;
+        videotestsrc.link(&appsink)?;
+        pipeline
+            .set_state(gst::State::Playing)
+            .map_err(|e| gst::glib::bool_error!("无法启动 videotestsrc: {}", e))?;
+        Ok(TestPatternSource { pipeline, appsink })
+    }
+
+    fn frame(&mut self) -> Result<gst::Buffer, gst::FlowError> {
+        let sample = self.appsink.pull_sample().map_err(|_| {
+            if self.appsink.is_eos() {
+                gst::FlowError::Eos
+            } else {
+                gst::FlowError::Error
+            }
+        })?;
+        sample.buffer_owned().ok_or(gst::FlowError::Error)
+    }
+}
+
+impl Drop for TestPatternSource {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+/// Session 的帧来源
+pub(crate) enum FrameSource {
+    Compositor(WaylandDisplay),
+    TestPattern(TestPatternSource),
+}
+
+impl FrameSource {
+    /// 获取当前画面，必须在创建帧来源的线程中调用
+    pub(crate) fn frame(&mut self) -> Result<gst::Buffer, gst::FlowError> {
+        match self {
+            FrameSource::Compositor(display) => display.frame(),
+            FrameSource::TestPattern(source) => source.frame(),
+        }
+    }
+
+    /// 合成器，用于注入输入；测试图案没有合成器
+    pub(crate) fn display(&self) -> Option<&WaylandDisplay> {
+        match self {
+            FrameSource::Compositor(display) => Some(display),
+            FrameSource::TestPattern(_) => None,
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! 异步取帧：在专用线程中运行合成器，通过广播通道把帧分发给多个订阅者
+//!
+//! WaylandDisplay::frame() 必须在创建它的线程中调用。DisplayThread 在自己的线程里创建
+//! Session 并按帧率取帧，其他线程（包括 tokio 任务）通过 subscribe() 得到一个
+//! futures::Stream。输入事件通过通道转发到显示线程执行。
+//!
+//! 每个订阅者有独立的接收队列，处理得太慢时会丢弃最旧的帧，并收到一个 Lagged 通知。
+
+use crate::error::{AppError, DisplayError};
+use crate::pacing::FramePacer;
+use crate::session::{Session, SessionBuilder, MAX_CONSECUTIVE_FAILURES};
+use futures::stream::{BoxStream, StreamExt};
+use gst_video::VideoInfo;
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+/// 每个订阅者最多缓存的帧数
+pub const DEFAULT_CAPACITY: usize = 4;
+
+/// 一帧画面
+#[derive(Debug)]
+pub struct Frame {
+    pub buffer: gst::Buffer,
+    /// 帧序号，从 0 开始；订阅者可以据此发现被丢弃的帧
+    pub sequence: u64,
+    /// 距离显示线程启动的时间
+    pub timestamp: Duration,
+    /// 取帧的时刻
+    pub captured_at: Instant,
+}
+
+/// 订阅者处理太慢，丢弃了 skipped 帧
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lagged {
+    pub skipped: u64,
+}
+
+/// 帧流，显示线程退出后结束
+pub type FrameStream = BoxStream<'static, Result<Arc<Frame>, Lagged>>;
+
+/// 转发到显示线程的命令
+enum Command {
+    Keyboard { key: u32, pressed: bool },
+    PointerMotion { dx: f64, dy: f64 },
+    PointerMotionAbsolute { x: f64, y: f64 },
+    PointerButton { button: u32, pressed: bool },
+    PointerAxis { x: f64, y: f64 },
+    Stop,
+}
+
+/// 在专用线程中运行的合成器
+pub struct DisplayThread {
+    env_vars: Vec<String>,
+    video_info: VideoInfo,
+    /// 唯一的 Sender 在显示线程中，线程退出时通道关闭，所有帧流随之结束
+    frames: broadcast::WeakSender<Arc<Frame>>,
+    commands: mpsc::Sender<Command>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl DisplayThread {
+    /// 启动显示线程，capacity 为每个订阅者最多缓存的帧数
+    /// builder 中的输出配置会被忽略，帧只通过 subscribe() 分发
+    pub fn spawn(builder: SessionBuilder, capacity: usize) -> Result<DisplayThread, AppError> {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        let frames = sender.downgrade();
+        let (commands, command_rx) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let handle = thread::Builder::new()
+            .name("weadless-display".to_string())
+            .spawn(move || {
+                let session = match builder.without_output().build() {
+                    Ok(session) => session,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                };
+                let _ = ready_tx.send(Ok((
+                    session.env_vars().to_vec(),
+                    session.video_info().clone(),
+                )));
+                run(session, sender, command_rx);
+            })
+            .expect("无法创建显示线程");
+
+        match ready_rx.recv() {
+            Ok(Ok((env_vars, video_info))) => Ok(DisplayThread {
+                env_vars,
+                video_info,
+                frames,
+                commands,
+                handle: Some(handle),
+            }),
+            Ok(Err(e)) => {
+                let _ = handle.join();
+                Err(e)
+            }
+            Err(_) => {
+                let _ = handle.join();
+                Err(AppError::Display(DisplayError::Create(
+                    "显示线程意外退出".to_string(),
+                )))
+            }
+        }
+    }
+
+    /// 订阅帧流；只有在至少一个订阅者存在时显示线程才会取帧
+    /// 显示线程已经退出时返回的帧流立即结束
+    pub fn subscribe(&self) -> FrameStream {
+        let Some(sender) = self.frames.upgrade() else {
+            return futures::stream::empty().boxed();
+        };
+        futures::stream::unfold(sender.subscribe(), |mut rx| async move {
+            match rx.recv().await {
+                Ok(frame) => Some((Ok(frame), rx)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "帧订阅者处理太慢，丢弃了最旧的帧");
+                    Some((Err(Lagged { skipped }), rx))
+                }
+                Err(broadcast::error::RecvError::Closed) => None,
+            }
+        })
+        .boxed()
+    }
+
+    /// 当前的订阅者数量
+    pub fn subscriber_count(&self) -> usize {
+        self.frames
+            .upgrade()
+            .map_or(0, |sender| sender.receiver_count())
+    }
+
+    /// Wayland 客户端需要的环境变量，格式为 KEY=VALUE
+    pub fn env_vars(&self) -> &[String] {
+        &self.env_vars
+    }
+
+    pub fn video_info(&self) -> &VideoInfo {
+        &self.video_info
+    }
+
+    pub fn keyboard_input(&self, key: u32, pressed: bool) {
+        let _ = self.commands.send(Command::Keyboard { key, pressed });
+    }
+
+    pub fn pointer_motion(&self, dx: f64, dy: f64) {
+        let _ = self.commands.send(Command::PointerMotion { dx, dy });
+    }
+
+    pub fn pointer_motion_absolute(&self, x: f64, y: f64) {
+        let _ = self
+            .commands
+            .send(Command::PointerMotionAbsolute { x, y });
+    }
+
+    pub fn pointer_button(&self, button: u32, pressed: bool) {
+        let _ = self.commands.send(Command::PointerButton { button, pressed });
+    }
+
+    pub fn pointer_axis(&self, x: f64, y: f64) {
+        let _ = self.commands.send(Command::PointerAxis { x, y });
+    }
+
+    /// 停止显示线程并等待它退出，所有帧流随之结束
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        let _ = self.commands.send(Command::Stop);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for DisplayThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// 显示线程的主循环
+fn run(
+    mut session: Session,
+    frames: broadcast::Sender<Arc<Frame>>,
+    commands: mpsc::Receiver<Command>,
+) {
+    // 停止信号通过命令通道传递，这里只用 FramePacer 的截止时间控制帧率
+    let (_stop_tx, stop_rx) = mpsc::channel::<()>();
+    let mut pacer = FramePacer::new(session.video_info().fps(), "帧流");
+    let start = Instant::now();
+    let mut sequence = 0u64;
+    let mut failures = 0u32;
+
+    info!("显示线程已启动");
+    'frames: while pacer.wait(&stop_rx) {
+        for command in commands.try_iter() {
+            match command {
+                Command::Keyboard { key, pressed } => session.keyboard_input(key, pressed),
+                Command::PointerMotion { dx, dy } => session.pointer_motion(dx, dy),
+                Command::PointerMotionAbsolute { x, y } => session.pointer_motion_absolute(x, y),
+                Command::PointerButton { button, pressed } => {
+                    session.pointer_button(button, pressed)
+                }
+                Command::PointerAxis { x, y } => session.pointer_axis(x, y),
+                Command::Stop => break 'frames,
+            }
+        }
+
+        // 没有订阅者时不取帧
+        if frames.receiver_count() == 0 {
+            continue;
+        }
+
+        match session.frame() {
+            Ok(buffer) => {
+                failures = 0;
+                let captured_at = Instant::now();
+                let frame = Frame {
+                    buffer,
+                    sequence,
+                    timestamp: captured_at - start,
+                    captured_at,
+                };
+                sequence += 1;
+                // 所有订阅者都已经离开时发送会失败，忽略即可
+                let _ = frames.send(Arc::new(frame));
+                pacer.frame_sent();
+            }
+            Err(e) if e.is_shutdown() => {
+                info!("{}", e);
+                break;
+            }
+            Err(e) => {
+                failures += 1;
+                if failures >= MAX_CONSECUTIVE_FAILURES {
+                    error!("连续 {} 次取帧失败，停止显示线程: {}", failures, e);
+                    break;
+                }
+                warn!("{}，继续尝试...", e);
+            }
+        }
+    }
+
+    session.shutdown();
+    info!("显示线程已退出");
+}
@@ -1,14 +1,138 @@
-//! 使用 GStreamer 查看 weadless compositor 输出流的客户端程序
+//! weadless 输出流的接收端
+//!
+//! 默认值与服务端一致：接收 127.0.0.1:5000 上的 UDP RTP 流，解码后显示在窗口中，并定期输出
+//! 帧率、码率和丢包统计。解包器和解码器由 decodebin 按流的编码格式自动选择。
 //!
 //! 使用方法：
-//!   1. 启动 weadless compositor
-//!   2. 设置 WAYLAND_DISPLAY 环境变量
-//!   3. 运行此程序：cargo run --bin viewer
+//!   viewer                                 # 服务端: weadless --output appsrc
+//!   viewer --tcp 192.168.1.10:5000         # 服务端: weadless --output appsrc --protocol tcp
+//!   viewer --udp 0.0.0.0:5000 --mux mpegts # 服务端: weadless --output appsrc --mux mpegts
+//!
+//! --latency 读出 weadless --latency-stamp 画在每帧中的时间条码，输出端到端延迟的百分位数。
+//! --input 把窗口中的键盘和鼠标事件发回服务端（weadless --input-listen），注入合成器。
+//! --record 把收到的流不经重新编码写入 MP4 或 MKV 文件，加上 --record-only 时不解码也不显示。
+//! --check 不显示画面，检查流中的帧数、帧率、码率和解码错误，用于 CI：在超时前达到所有阈值时
+//! 以 0 退出，否则以 1 退出。
+//! --local 不经过编码和网络，在进程内启动合成器（见 weadless::Session）并直接显示画面，
+//! 适合调试 Wayland 应用。
 
+use clap::Parser;
 use gst::prelude::*;
-use gst_app::AppSrc;
+use gst_app::{AppSink, AppSrc};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
-use tracing::info;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, error, info, warn};
+use weadless::input::{self, InputEvent, InputProtocol, InputSender};
+use weadless::latency::{self, LatencyStats};
+use weadless::receiver::{
+    Mux, Receiver, ReceiverError, ReceiverOptions, ReceiverState, ReceiverStats, Transport,
+};
+use weadless::Session;
+
+/// 检查退出信号和总线消息的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "接收并显示 weadless 的输出流", long_about = None)]
+struct Args {
+    /// 接收 UDP 流的本地地址（服务端的 --output-address），不指定 --tcp 时默认使用 127.0.0.1:5000
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "tcp")]
+    udp: Option<String>,
+
+    /// 连接服务端的 TCP 输出（服务端的 --output-address）
+    #[arg(long, value_name = "ADDRESS")]
+    tcp: Option<String>,
+
+    /// 封装格式，与服务端的 --mux 一致
+    #[arg(long, value_enum, default_value = "rtp")]
+    mux: Mux,
+
+    /// RTP 的编码名称，用于选择解包器
+    #[arg(long, default_value = "H264")]
+    encoding_name: String,
+
+    /// RTP 抖动缓冲（毫秒）
+    #[arg(long, default_value_t = 200)]
+    jitter_latency: u32,
+
+    /// 读出 weadless --latency-stamp 画出的时间条码，统计端到端延迟（不显示画面）
+    #[arg(long)]
+    latency: bool,
+
+    /// 统计信息的输出间隔（秒）
+    #[arg(long, default_value_t = 5)]
+    report_interval: u64,
+
+    /// 把窗口中的键盘和鼠标事件发送到服务端的 --input-listen 地址
+    #[arg(long, value_name = "ADDRESS", conflicts_with_all = ["latency", "check", "local"])]
+    input: Option<String>,
+
+    /// 输入通道的传输协议，与服务端的 --input-protocol 一致
+    #[arg(long, value_enum, default_value = "tcp")]
+    input_protocol: InputProtocol,
+
+    /// 把收到的流不经重新编码写入文件，按扩展名使用 MP4（.mp4）或 Matroska（.mkv）；
+    /// 流的格式变化时切换到新文件（name-1.mp4、name-2.mp4……）
+    #[arg(long, value_name = "PATH", conflicts_with = "local")]
+    record: Option<PathBuf>,
+
+    /// 只录制，不解码也不显示（需要 --record）
+    #[arg(long, requires = "record", conflicts_with_all = ["latency", "check", "input"])]
+    record_only: bool,
+
+    /// 不显示画面，检查流是否达到下面的阈值，达到时以 0 退出，超时以 1 退出（用于 CI）
+    #[arg(long, conflicts_with = "latency")]
+    check: bool,
+
+    /// 至少解码的帧数（--check 时使用）
+    #[arg(long, default_value_t = 30)]
+    min_frames: u64,
+
+    /// 最低帧率（--check 时使用，从第一帧开始至少测量 1 秒）
+    #[arg(long)]
+    min_fps: Option<f64>,
+
+    /// 最低码率（kbps，--check 时使用，包括 RTP 或 TS 封装）
+    #[arg(long)]
+    min_bitrate: Option<u32>,
+
+    /// 允许的解码错误数（--check 时使用）
+    #[arg(long, default_value_t = 0)]
+    max_decode_errors: u64,
+
+    /// 等待达到阈值的最长时间（秒，--check 时使用）
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+
+    /// 把解码后的第 N 帧（从 1 开始）保存为 PNG（--check 时使用）
+    #[arg(long, value_name = "N")]
+    dump_frame: Option<u64>,
+
+    /// --dump-frame 的保存路径
+    #[arg(long, default_value = "frame.png")]
+    dump_path: PathBuf,
+
+    /// 不接收网络流，在进程内启动合成器并直接显示画面
+    #[arg(long, conflicts_with_all = ["udp", "tcp", "latency", "check"])]
+    local: bool,
+
+    /// 渲染节点路径（例如 /dev/dri/renderD128），使用 "software" 进行软件渲染（--local 时使用）
+    #[arg(long, default_value = "software")]
+    render_node: String,
+
+    /// 输出宽度（像素，--local 时使用）
+    #[arg(long, default_value_t = 1920)]
+    width: u32,
+
+    /// 输出高度（像素，--local 时使用）
+    #[arg(long, default_value_t = 1080)]
+    height: u32,
+
+    /// 帧率，支持整数、分数（60000/1001）和小数（29.97）（--local 时使用）
+    #[arg(long, default_value = "60", value_parser = weadless::framerate::parse_frame_rate)]
+    fps: gst::Fraction,
+}
 
 fn main() {
     // 初始化日志
@@ -19,77 +143,639 @@ fn main() {
         )
         .init();
 
-    // 初始化 GStreamer
-    gst::init().expect("Failed to initialize GStreamer");
+    let args = Args::parse();
 
-    info!("连接到 weadless compositor...");
+    if args.local {
+        run_local(&args);
+        return;
+    }
 
-    // 从环境变量获取 WAYLAND_DISPLAY
-    let wayland_display = std::env::var("WAYLAND_DISPLAY")
-        .expect("WAYLAND_DISPLAY 环境变量未设置");
+    if let Err(e) = gst::init() {
+        eprintln!("错误: 无法初始化 GStreamer: {}", e);
+        std::process::exit(1);
+    }
+    let input = args.input.as_ref().map(|address| {
+        InputSender::connect(args.input_protocol, address).unwrap_or_else(|e| {
+            eprintln!("错误: {}", e);
+            eprintln!("提示: 确认服务端使用了 --input-listen {}", address);
+            std::process::exit(1);
+        })
+    });
+    let result = if args.check {
+        run_check(&args)
+    } else {
+        run_receiver(&args, input)
+    };
+    match result {
+        Ok(()) => {}
+        // 检查结果已经输出到标准输出
+        Err(ReceiverError::CheckFailed(_)) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("错误: {}", e);
+            if let Some(hint) = e.hint() {
+                eprintln!("提示: {}", hint);
+            }
+            std::process::exit(1);
+        }
+    }
+}
 
-    info!("WAYLAND_DISPLAY={}", wayland_display);
+fn receiver_options(args: &Args) -> ReceiverOptions {
+    let defaults = ReceiverOptions::default();
+    let (transport, address) = match (&args.udp, &args.tcp) {
+        (_, Some(address)) => (Transport::Tcp, address.clone()),
+        (Some(address), None) => (Transport::Udp, address.clone()),
+        (None, None) => (Transport::Udp, defaults.address),
+    };
+    ReceiverOptions {
+        transport,
+        address,
+        mux: args.mux,
+        encoding_name: args.encoding_name.clone(),
+        jitter_latency: args.jitter_latency,
+        record: args.record.clone(),
+    }
+}
 
-    // 创建 WaylandDisplay（连接到现有的 compositor）
-    // 注意：这里我们需要连接到已运行的 compositor
-    // 但实际上，我们需要从 compositor 获取输出流
-    // 让我们创建一个简单的 viewer，通过 frame() 方法获取帧并显示
+fn make_element(name: &'static str) -> Result<gst::Element, ReceiverError> {
+    gst::ElementFactory::make(name)
+        .build()
+        .map_err(|source| ReceiverError::Element { name, source })
+}
 
-    // 由于 WaylandDisplay::new 会创建新的 compositor，
-    // 我们需要另一种方式来获取输出流
-    // 实际上，compositor 的输出应该通过 GStreamer pipeline 暴露
+/// 接收网络流，按 Ctrl+C 或流结束后输出总计并退出
+fn run_receiver(args: &Args, input: Option<InputSender>) -> Result<(), ReceiverError> {
+    let options = receiver_options(args);
+    let interval = Duration::from_secs(args.report_interval.max(1));
 
-    // 创建 GStreamer pipeline 来显示 compositor 的输出
-    // 注意：这需要 compositor 通过 appsrc 或其他方式暴露输出流
-    // 由于 wayland-display-core 的设计，输出流可能已经通过内部 pipeline 暴露
+    // 测量延迟时解码到 appsink，条码只需要亮度
+    let appsink = args.latency.then(|| {
+        AppSink::builder()
+            .caps(
+                &gst::Caps::builder("video/x-raw")
+                    .field("format", "GRAY8")
+                    .build(),
+            )
+            .sync(false)
+            .max_buffers(2)
+            .drop(true)
+            .build()
+    });
+    // 只录制时不解码
+    let sink = if args.record_only {
+        Vec::new()
+    } else {
+        let sink = match &appsink {
+            Some(appsink) => appsink.clone().upcast(),
+            None => gst::ElementFactory::make("autovideosink")
+                .property("sync", false)
+                .build()
+                .map_err(|source| ReceiverError::Element {
+                    name: "autovideosink",
+                    source,
+                })?,
+        };
+        let videoconvert = make_element("videoconvert")?;
+        if let Some(input) = input {
+            forward_navigation(&videoconvert, input);
+        }
+        vec![videoconvert, sink]
+    };
+    let receiver = Receiver::start(&options, &sink)?;
 
-    // 让我们尝试使用 waylandsrc 来捕获 compositor 的输出
-    // 但实际上，我们需要的是从 compositor 获取帧数据
+    let (tx, rx) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = tx.send(());
+    })
+    .expect("无法设置 Ctrl+C 处理器");
+    info!("按 Ctrl+C 退出");
 
-    // 创建一个简单的测试：使用 appsrc 来接收帧数据
-    let pipeline = gst::Pipeline::new();
+    let started = Instant::now();
+    let mut meter = LatencyMeter::default();
+    let mut last_report = Instant::now();
+    let mut last_stats = ReceiverStats::default();
+    let mut result = Ok(());
+
+    while rx.try_recv().is_err() {
+        match &appsink {
+            Some(appsink) => {
+                let timeout = gst::ClockTime::from_mseconds(POLL_INTERVAL.as_millis() as u64);
+                if let Some(sample) = appsink.try_pull_sample(timeout) {
+                    meter.record(&sample);
+                }
+            }
+            None => std::thread::sleep(POLL_INTERVAL),
+        }
+
+        match receiver.poll() {
+            Ok(ReceiverState::Running) => {}
+            Ok(ReceiverState::Eos) => {
+                info!("流已结束");
+                break;
+            }
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+
+        if last_report.elapsed() >= interval {
+            let stats = receiver.stats();
+            log_stats(&stats.since(&last_stats), last_report.elapsed());
+            if args.latency {
+                meter.report(interval);
+            }
+            last_stats = stats;
+            last_report = Instant::now();
+        }
+    }
+
+    let stats = receiver.stats();
+    receiver.stop();
+    if stats.frames > 0 {
+        info!("总计: {} 帧", stats.frames);
+        log_stats(&stats, started.elapsed());
+    }
+    if args.latency {
+        meter.finish();
+    }
+    result
+}
+
+fn log_stats(stats: &ReceiverStats, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    info!(
+        "{:.1} fps，{:.0} kbps，丢包 {}，解码错误 {}",
+        stats.frames as f64 / secs,
+        stats.bytes as f64 * 8.0 / 1000.0 / secs,
+        stats.lost,
+        stats.decode_errors
+    );
+}
+
+/// 测量帧率和码率的最短时间
+const MEASURE_PERIOD: Duration = Duration::from_secs(1);
+
+/// --check：解码到 appsink，直到所有阈值都达到、解码错误超过上限或者超时
+/// 没有通过检查时返回 ReceiverError::CheckFailed
+fn run_check(args: &Args) -> Result<(), ReceiverError> {
+    let options = receiver_options(args);
+    let appsink = AppSink::builder().sync(false).max_buffers(2).build();
+    let receiver = Receiver::start(
+        &options,
+        &[make_element("videoconvert")?, appsink.clone().upcast()],
+    )?;
 
-    // 创建 appsrc 元素
+    let (tx, rx) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = tx.send(());
+    })
+    .expect("无法设置 Ctrl+C 处理器");
+
+    let deadline = Instant::now() + Duration::from_secs(args.timeout);
+    let timeout = gst::ClockTime::from_mseconds(POLL_INTERVAL.as_millis() as u64);
+    // 第一帧到达的时间和当时的统计，帧率和码率从这里开始计算，不包括等待连接和关键帧的时间
+    let mut first: Option<(Instant, ReceiverStats)> = None;
+    let mut pulled = 0u64;
+    let mut dumped = args.dump_frame.is_none();
+    let mut failures = Vec::new();
+
+    loop {
+        if let Some(sample) = appsink.try_pull_sample(timeout) {
+            pulled += 1;
+            if first.is_none() {
+                info!("收到第一帧");
+                first = Some((Instant::now(), receiver.stats()));
+            }
+            if args.dump_frame == Some(pulled) {
+                match dump_png(&sample, &args.dump_path) {
+                    Ok(()) => info!("第 {} 帧已保存到 {}", pulled, args.dump_path.display()),
+                    Err(e) => failures.push(format!("无法保存第 {} 帧: {}", pulled, e)),
+                }
+                dumped = true;
+            }
+        }
+
+        let ended = match receiver.poll() {
+            Ok(state) => state == ReceiverState::Eos,
+            Err(e) => {
+                failures.push(e.to_string());
+                true
+            }
+        };
+
+        let stats = receiver.stats();
+        let rates = first.and_then(|(since, earlier)| {
+            let elapsed = since.elapsed();
+            let delta = stats.since(&earlier);
+            (elapsed >= MEASURE_PERIOD).then(|| {
+                let secs = elapsed.as_secs_f64();
+                (
+                    delta.frames as f64 / secs,
+                    delta.bytes as f64 * 8.0 / 1000.0 / secs,
+                )
+            })
+        });
+        if stats.decode_errors > args.max_decode_errors {
+            failures.push(format!(
+                "解码错误 {} 次，超过 --max-decode-errors {}",
+                stats.decode_errors, args.max_decode_errors
+            ));
+        }
+
+        let unmet = unmet_thresholds(args, &stats, rates, dumped);
+        let stop = !failures.is_empty() || unmet.is_empty() || ended || rx.try_recv().is_ok();
+        if stop || Instant::now() >= deadline {
+            if !unmet.is_empty() && failures.is_empty() {
+                if ended {
+                    failures.push("流在达到阈值前结束".to_string());
+                } else if Instant::now() >= deadline {
+                    failures.push(format!("{} 秒内没有达到阈值", args.timeout));
+                } else {
+                    failures.push("检查被中断".to_string());
+                }
+                failures.extend(unmet);
+            }
+            break;
+        }
+    }
+
+    let stats = receiver.stats();
+    receiver.stop();
+    let (fps, kbps) = first
+        .map(|(since, earlier)| {
+            let secs = since.elapsed().as_secs_f64().max(f64::EPSILON);
+            let delta = stats.since(&earlier);
+            (
+                delta.frames as f64 / secs,
+                delta.bytes as f64 * 8.0 / 1000.0 / secs,
+            )
+        })
+        .unwrap_or_default();
+    let summary = format!(
+        "{} 帧，{:.1} fps，{:.0} kbps，丢包 {}，解码错误 {}",
+        stats.frames, fps, kbps, stats.lost, stats.decode_errors
+    );
+    if failures.is_empty() {
+        println!("检查通过: {}", summary);
+        Ok(())
+    } else {
+        println!("检查失败: {}", summary);
+        for failure in &failures {
+            println!("  - {}", failure);
+        }
+        Err(ReceiverError::CheckFailed(failures))
+    }
+}
+
+/// 尚未达到的阈值；帧率和码率在测量时间不足时视为未达到
+fn unmet_thresholds(
+    args: &Args,
+    stats: &ReceiverStats,
+    rates: Option<(f64, f64)>,
+    dumped: bool,
+) -> Vec<String> {
+    let mut unmet = Vec::new();
+    if stats.frames < args.min_frames {
+        unmet.push(format!(
+            "帧数 {} < --min-frames {}",
+            stats.frames, args.min_frames
+        ));
+    }
+    if let Some(min_fps) = args.min_fps {
+        match rates {
+            Some((fps, _)) if fps >= min_fps => {}
+            Some((fps, _)) => unmet.push(format!("帧率 {:.1} < --min-fps {}", fps, min_fps)),
+            None => unmet.push("帧率测量时间不足".to_string()),
+        }
+    }
+    if let Some(min_bitrate) = args.min_bitrate {
+        match rates {
+            Some((_, kbps)) if kbps >= min_bitrate as f64 => {}
+            Some((_, kbps)) => unmet.push(format!(
+                "码率 {:.0} kbps < --min-bitrate {}",
+                kbps, min_bitrate
+            )),
+            None => unmet.push("码率测量时间不足".to_string()),
+        }
+    }
+    if !dumped {
+        unmet.push(format!(
+            "还没有收到第 {} 帧",
+            args.dump_frame.unwrap_or_default()
+        ));
+    }
+    unmet
+}
+
+/// 把解码后的画面编码为 PNG 并保存
+fn dump_png(sample: &gst::Sample, path: &Path) -> Result<(), String> {
+    let png = gst_video::convert_sample(
+        sample,
+        &gst::Caps::builder("image/png").build(),
+        gst::ClockTime::from_seconds(5),
+    )
+    .map_err(|e| e.to_string())?;
+    let buffer = png.buffer().ok_or("转换后的画面为空")?;
+    let map = buffer.map_readable().map_err(|e| e.to_string())?;
+    std::fs::write(path, map.as_slice()).map_err(|e| e.to_string())
+}
+
+/// 滚轮转动一格对应的 Wayland 滚动距离
+const SCROLL_STEP: f64 = 15.0;
+
+/// 视频窗口产生的导航事件向上游传递，在 videoconvert 的输出上截取并发回服务端
+fn forward_navigation(videoconvert: &gst::Element, input: InputSender) {
+    let pad = videoconvert
+        .static_pad("src")
+        .expect("videoconvert 没有 src pad");
+    pad.add_probe(gst::PadProbeType::EVENT_UPSTREAM, move |pad, info| {
+        let Some(gst::PadProbeData::Event(event)) = &info.data else {
+            return gst::PadProbeReturn::Ok;
+        };
+        if event.type_() != gst::EventType::Navigation {
+            return gst::PadProbeReturn::Ok;
+        }
+        let Ok(navigation) = gst_video::NavigationEvent::parse(event) else {
+            return gst::PadProbeReturn::Ok;
+        };
+        // 导航事件中的坐标已经由视频 sink 换算为画面像素坐标
+        let size = pad
+            .current_caps()
+            .and_then(|caps| gst_video::VideoInfo::from_caps(&caps).ok())
+            .map(|info| (info.width() as f64, info.height() as f64));
+        for event in to_input_events(&navigation, size) {
+            input.send(event);
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
+
+/// 把 GStreamer 导航事件转换为输入事件，size 为画面尺寸，用于归一化指针坐标
+fn to_input_events(
+    navigation: &gst_video::NavigationEvent,
+    size: Option<(f64, f64)>,
+) -> Vec<InputEvent> {
+    use gst_video::NavigationEvent as Nav;
+
+    let motion = |x: f64, y: f64| {
+        size.map(|(width, height)| InputEvent::Motion {
+            x: x / width,
+            y: y / height,
+        })
+    };
+    let key = |name: &str, pressed: bool| match input::evdev_key(name) {
+        Some(key) => Some(InputEvent::Key { key, pressed }),
+        None => {
+            debug!("不支持的按键: {}", name);
+            None
+        }
+    };
+    let button = |button: i32, pressed: bool| {
+        // X11 约定中 4~7 是滚轮，只在按下时产生一次滚动
+        let step = |dx: f64, dy: f64| pressed.then_some(InputEvent::Axis { dx, dy });
+        match button {
+            4 => step(0.0, -SCROLL_STEP),
+            5 => step(0.0, SCROLL_STEP),
+            6 => step(-SCROLL_STEP, 0.0),
+            7 => step(SCROLL_STEP, 0.0),
+            _ => input::evdev_button(button).map(|button| InputEvent::Button { button, pressed }),
+        }
+    };
+
+    match navigation {
+        Nav::KeyPress { key: name, .. } => key(name, true).into_iter().collect(),
+        Nav::KeyRelease { key: name, .. } => key(name, false).into_iter().collect(),
+        Nav::MouseMove { x, y, .. } => motion(*x, *y).into_iter().collect(),
+        Nav::MouseButtonPress {
+            button: b, x, y, ..
+        } => motion(*x, *y).into_iter().chain(button(*b, true)).collect(),
+        Nav::MouseButtonRelease {
+            button: b, x, y, ..
+        } => motion(*x, *y)
+            .into_iter()
+            .chain(button(*b, false))
+            .collect(),
+        // GStreamer 中向上滚动为正，Wayland 中向下滚动为正
+        Nav::MouseScroll {
+            x,
+            y,
+            delta_x,
+            delta_y,
+            ..
+        } => motion(*x, *y)
+            .into_iter()
+            .chain(Some(InputEvent::Axis {
+                dx: -delta_x * SCROLL_STEP,
+                dy: -delta_y * SCROLL_STEP,
+            }))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// 延迟样本：最近一个统计周期的和全部的
+#[derive(Default)]
+struct LatencyMeter {
+    window: LatencyStats,
+    total: LatencyStats,
+    unreadable: u64,
+}
+
+impl LatencyMeter {
+    fn record(&mut self, sample: &gst::Sample) {
+        // 收到画面的时间，在读取条码之前取得
+        let received = SystemTime::now();
+        match read_sample(sample) {
+            Some(sent) => {
+                // 时间倒退说明两端的时钟不一致，不计入统计
+                if let Ok(latency) = received.duration_since(sent) {
+                    self.window.record(latency);
+                    self.total.record(latency);
+                }
+            }
+            None => self.unreadable += 1,
+        }
+    }
+
+    fn report(&mut self, interval: Duration) {
+        match self.window.summary() {
+            Some(summary) => info!("延迟: {}", summary),
+            None => warn!("{} 秒内没有收到带条码的画面", interval.as_secs()),
+        }
+        self.window.clear();
+    }
+
+    fn finish(&self) {
+        if self.unreadable > 0 {
+            warn!(
+                "{} 帧没有读出条码，服务端是否使用了 --latency-stamp？",
+                self.unreadable
+            );
+        }
+        if let Some(summary) = self.total.summary() {
+            println!("总计: {}", summary);
+        }
+    }
+}
+
+/// 从 GRAY8 画面中读出发送时间
+fn read_sample(sample: &gst::Sample) -> Option<SystemTime> {
+    let caps = sample.caps()?;
+    let info = gst_video::VideoInfo::from_caps(caps).ok()?;
+    let buffer = sample.buffer()?;
+    let frame = gst_video::VideoFrameRef::from_buffer_ref_readable(buffer, &info).ok()?;
+    let stride = frame.plane_stride()[0] as usize;
+    latency::read(
+        frame.plane_data(0).ok()?,
+        stride,
+        info.width(),
+        info.height(),
+    )
+}
+
+/// 在进程内启动合成器，把取到的帧直接交给 autovideosink 显示
+fn run_local(args: &Args) {
+    let mut session = match Session::builder()
+        .render_node(args.render_node.clone())
+        .size(args.width, args.height)
+        .fps(args.fps)
+        .build()
+    {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("错误: {}", e);
+            if let Some(hint) = e.hint() {
+                eprintln!("提示: {}", hint);
+            }
+            std::process::exit(e.exit_code());
+        }
+    };
+
+    if let Some(socket) = session.wayland_display() {
+        println!("\n✓ Wayland compositor 已启动");
+        println!("  WAYLAND_DISPLAY={} weston-terminal", socket);
+        println!();
+    }
+
+    // appsrc ! videoconvert ! autovideosink
+    let video_info = session.video_info().clone();
+    let pipeline = gst::Pipeline::new();
     let appsrc = AppSrc::builder()
         .name("source")
-        .caps(&gst::Caps::builder("video/x-raw")
-            .field("format", "RGBx")
-            .field("width", 1920i32)
-            .field("height", 1080i32)
-            .field("framerate", gst::Fraction::new(60, 1))
-            .build())
+        .caps(&video_info.to_caps().expect("无法创建 caps"))
+        .format(gst::Format::Time)
+        .is_live(true)
+        .do_timestamp(true)
         .build();
-
-    // 创建 videoconvert 和 autovideosink
     let videoconvert = gst::ElementFactory::make("videoconvert").build().unwrap();
-    let sink = gst::ElementFactory::make("autovideosink").build().unwrap();
+    let sink = gst::ElementFactory::make("autovideosink")
+        .property("sync", false)
+        .build()
+        .unwrap();
 
-    // 添加元素到 pipeline
-    pipeline.add_many(&[appsrc.upcast_ref(), &videoconvert, &sink]).unwrap();
-    gst::Element::link_many(&[appsrc.upcast_ref(), &videoconvert, &sink]).unwrap();
-
-    info!("GStreamer pipeline 已创建");
-    info!("注意：此示例需要修改 weadless 以通过 appsrc 暴露输出流");
-    info!("或者使用其他方式（如共享内存、socket）来传输帧数据");
-
-    // 启动 pipeline
+    pipeline
+        .add_many([appsrc.upcast_ref(), &videoconvert, &sink])
+        .unwrap();
+    gst::Element::link_many([appsrc.upcast_ref(), &videoconvert, &sink]).unwrap();
     pipeline.set_state(gst::State::Playing).unwrap();
 
-    info!("Pipeline 正在运行...");
-    info!("按 Ctrl+C 退出");
+    info!("预览窗口已打开，按 Ctrl+C 退出");
 
-    // 等待退出信号
     let (tx, rx) = mpsc::channel();
     ctrlc::set_handler(move || {
         info!("收到退出信号，正在关闭...");
-        tx.send(()).unwrap();
+        let _ = tx.send(());
     })
     .expect("无法设置 Ctrl+C 处理器");
 
-    rx.recv().unwrap();
+    let fps = video_info.fps();
+    let interval = Duration::from_secs_f64(fps.denom() as f64 / fps.numer().max(1) as f64);
+    while let Err(mpsc::RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+        match session.frame() {
+            Ok(buffer) => {
+                if let Err(e) = appsrc.push_buffer(buffer) {
+                    error!("推送 buffer 失败: {:?}", e);
+                    break;
+                }
+            }
+            Err(e) if e.is_shutdown() => break,
+            Err(e) => warn!("{}，继续尝试...", e),
+        }
+    }
 
-    // 停止 pipeline
+    let _ = appsrc.end_of_stream();
     pipeline.set_state(gst::State::Null).unwrap();
+    session.shutdown();
     info!("已退出");
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 只检查帧率和码率
+    const RATE_ARGS: &[&str] = &[
+        "--min-frames",
+        "0",
+        "--min-fps",
+        "25",
+        "--min-bitrate",
+        "200",
+    ];
+
+    fn check_args(extra: &[&str]) -> Args {
+        Args::parse_from(["viewer", "--check"].iter().chain(extra))
+    }
+
+    #[test]
+    fn all_thresholds_met() {
+        let args = check_args(RATE_ARGS);
+        let stats = ReceiverStats::default();
+        assert!(unmet_thresholds(&args, &stats, Some((30.0, 500.0)), true).is_empty());
+    }
+
+    #[test]
+    fn rates_need_a_full_measurement() {
+        let args = check_args(RATE_ARGS);
+        let unmet = unmet_thresholds(&args, &ReceiverStats::default(), None, true);
+        assert_eq!(unmet, ["帧率测量时间不足", "码率测量时间不足"]);
+    }
+
+    #[test]
+    fn reports_low_rates() {
+        let args = check_args(RATE_ARGS);
+        let unmet = unmet_thresholds(&args, &ReceiverStats::default(), Some((20.0, 100.0)), true);
+        assert_eq!(
+            unmet,
+            [
+                "帧率 20.0 < --min-fps 25",
+                "码率 100 kbps < --min-bitrate 200"
+            ]
+        );
+    }
+
+    #[test]
+    fn waits_for_dump_frame() {
+        let args = check_args(&["--min-frames", "0", "--dump-frame", "50"]);
+        let stats = ReceiverStats::default();
+        assert_eq!(
+            unmet_thresholds(&args, &stats, None, false),
+            ["还没有收到第 50 帧"]
+        );
+        assert!(unmet_thresholds(&args, &stats, None, true).is_empty());
+    }
+
+    #[test]
+    fn counts_frames() {
+        let args = check_args(&[]);
+        let stats = ReceiverStats {
+            frames: 29,
+            ..ReceiverStats::default()
+        };
+        assert_eq!(
+            unmet_thresholds(&args, &stats, None, true),
+            ["帧数 29 < --min-frames 30"]
+        );
+    }
+}
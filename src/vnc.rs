@@ -0,0 +1,428 @@
+//! VNC 输出：把合成器的画面转换为 RGB888 后交给 rustvncserver
+//!
+//! rustvncserver 的 listen() 只能监听 0.0.0.0，这里自己在 --bind-address 上监听，
+//! 每个客户端连接时让服务器反向连接一个临时的本机回环端口，再在两个连接之间转发数据。
+
+use crate::clients::ClientTracker;
+use crate::crop::CropRect;
+use crate::scale::{self, ScaleMethod, ScaleOptions};
+use gst::glib;
+use gst_video::{
+    VideoColorMatrix, VideoColorRange, VideoColorimetry, VideoFormat, VideoFrameRef, VideoInfo,
+};
+use rustvncserver::{ServerEvent, VncServer};
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// 停止时等待 runtime 中剩余任务结束的最长时间
+const STOP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// VNC 输出选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VncOptions {
+    /// 监听地址，0.0.0.0 或 :: 表示所有网络接口
+    /// 其中的端口被忽略，使用 port；带 zone ID 的 IPv6 地址保留 scope ID
+    pub bind_address: SocketAddr,
+    pub port: u16,
+    /// 为 None 时不需要密码
+    pub password: Option<String>,
+    /// 裁剪区域，None 表示输出整个画面
+    pub crop: Option<CropRect>,
+    /// 帧缓冲区分辨率，None 表示与合成器（或裁剪区域）相同
+    pub scale: Option<ScaleOptions>,
+}
+
+impl VncOptions {
+    /// 检查参数取值
+    pub fn validate(&self) -> Result<(), VncError> {
+        if let Some(scale) = &self.scale {
+            scale.validate(false).map_err(VncError::InvalidOption)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for VncOptions {
+    fn default() -> VncOptions {
+        VncOptions {
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 0)),
+            port: 5900,
+            password: None,
+            crop: None,
+            scale: None,
+        }
+    }
+}
+
+/// VNC 输出错误
+#[derive(Debug)]
+pub enum VncError {
+    /// 无法监听指定的地址和端口
+    Listen {
+        address: SocketAddr,
+        source: std::io::Error,
+    },
+    /// 无法创建运行 VNC 服务器的 tokio runtime
+    Runtime(std::io::Error),
+    /// 无法映射帧数据
+    Map(glib::BoolError),
+    /// 视频格式无法转换为 RGB888
+    UnsupportedFormat(VideoFormat),
+    /// 更新帧缓冲区失败
+    Update(String),
+    /// 缩放尺寸或裁剪区域无效
+    InvalidOption(String),
+}
+
+impl VncError {
+    /// 给用户的处理建议
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            VncError::Listen { .. } => {
+                Some("确认端口没有被占用（--vnc-port），--bind-address 是本机的地址".to_string())
+            }
+            VncError::UnsupportedFormat(_) => Some(
+                "VNC 输出请使用 --format RGBx、RGBA、BGRx、BGRA、RGB、NV12 或 I420".to_string(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for VncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VncError::Listen { address, source } => {
+                write!(f, "VNC 服务器无法监听 {}: {}", address, source)
+            }
+            VncError::Runtime(e) => write!(f, "无法创建 tokio runtime: {}", e),
+            VncError::Map(e) => write!(f, "无法映射 buffer: {}", e),
+            VncError::UnsupportedFormat(format) => {
+                write!(f, "VNC 输出无法转换视频格式 {:?}", format)
+            }
+            VncError::Update(e) => write!(f, "无法更新 VNC 帧缓冲区: {}", e),
+            VncError::InvalidOption(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for VncError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VncError::Runtime(e) | VncError::Listen { source: e, .. } => Some(e),
+            VncError::Map(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// 正在运行的 VNC 输出
+pub struct VncOutput {
+    server: Arc<VncServer>,
+    crop: Option<CropRect>,
+    /// 帧缓冲区尺寸，在创建 VNC 服务器时确定
+    framebuffer: (u32, u32),
+    method: ScaleMethod,
+    /// 运行 VNC 服务器的 runtime，停止后为 None
+    runtime: Option<Runtime>,
+    /// 接受连接的任务，结束时释放监听端口
+    accept: JoinHandle<()>,
+    /// 处理服务器事件的任务，以及通知它结束的通道
+    events: JoinHandle<()>,
+    events_stop: Option<oneshot::Sender<()>>,
+}
+
+impl VncOutput {
+    /// 把一帧画面转换为 RGB888（需要时裁剪和缩放）并更新帧缓冲区
+    pub fn send_frame(&self, buffer: &gst::Buffer, video_info: &VideoInfo) -> Result<(), VncError> {
+        // 按 video_info 映射 buffer，得到每个平面的数据和行跨度
+        let frame = VideoFrameRef::from_buffer_ref_readable(buffer.as_ref(), video_info)
+            .map_err(VncError::Map)?;
+        let region = self.crop.unwrap_or(CropRect {
+            x: 0,
+            y: 0,
+            width: video_info.width(),
+            height: video_info.height(),
+        });
+
+        // VNC 需要 RGB888 格式（每个像素 3 字节）
+        let mut rgb_data = to_rgb(&frame, &region)?;
+        // 裁剪区域与帧缓冲区尺寸不同时（启用了缩放，或者运行中修改了裁剪区域的尺寸）按比例缩放
+        let (width, height) = self.framebuffer;
+        if (region.width, region.height) != (width, height) {
+            let scale = ScaleOptions {
+                width,
+                height,
+                method: self.method,
+            };
+            rgb_data = scale::scale_rgb(&rgb_data, region.width, region.height, &scale);
+        }
+
+        // 发送帧到 VNC 服务器
+        self.server
+            .update_framebuffer(0, 0, width as u16, height as u16, &rgb_data)
+            .map_err(|e| VncError::Update(format!("{:?}", e)))?;
+
+        Ok(())
+    }
+
+    /// 修改裁剪区域，None 表示输出整个画面
+    /// 帧缓冲区尺寸不变，区域尺寸不同时画面按比例缩放到帧缓冲区中
+    pub fn set_crop(
+        &mut self,
+        crop: Option<CropRect>,
+        video_info: &VideoInfo,
+    ) -> Result<(), VncError> {
+        if let Some(crop) = &crop {
+            validate_crop(crop, video_info)?;
+            info!("VNC 裁剪区域已修改为 {}", crop);
+        } else {
+            info!("VNC 已取消裁剪");
+        }
+        self.crop = crop;
+        Ok(())
+    }
+
+    /// 停止 VNC 服务器：不再接受连接，断开所有客户端并释放监听端口
+    pub fn stop(&mut self) {
+        let Some(runtime) = self.runtime.take() else {
+            return;
+        };
+        self.accept.abort();
+        if let Some(stop) = self.events_stop.take() {
+            let _ = stop.send(());
+        }
+        runtime.block_on(async {
+            self.server.disconnect_all_clients().await;
+            let _ = (&mut self.events).await;
+            let _ = (&mut self.accept).await;
+        });
+        runtime.shutdown_timeout(STOP_TIMEOUT);
+        info!("VNC 服务器已停止");
+    }
+}
+
+impl Drop for VncOutput {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// 逐像素转换时按坐标取色度，VNC 的裁剪区域不需要对齐到偶数
+fn validate_crop(crop: &CropRect, video_info: &VideoInfo) -> Result<(), VncError> {
+    crop.validate(video_info.width(), video_info.height(), false)
+        .map_err(VncError::InvalidOption)
+}
+
+/// 使用 VNC 服务器方式暴露输出流
+/// 注意：frame() 必须在创建 WaylandDisplay 的线程中调用
+pub fn start_vnc_output(
+    video_info: VideoInfo,
+    options: &VncOptions,
+    clients: ClientTracker,
+) -> Result<VncOutput, VncError> {
+    options.validate()?;
+    let mut address = options.bind_address;
+    address.set_port(options.port);
+
+    if let Some(crop) = &options.crop {
+        validate_crop(crop, &video_info)?;
+    }
+    // 帧缓冲区尺寸依次取缩放尺寸、裁剪区域尺寸和合成器分辨率
+    let framebuffer = match (&options.scale, &options.crop) {
+        (Some(scale), _) => (scale.width, scale.height),
+        (None, Some(crop)) => (crop.width, crop.height),
+        (None, None) => (video_info.width(), video_info.height()),
+    };
+    let (width, height) = (framebuffer.0 as u16, framebuffer.1 as u16);
+    let name = "weadless".to_string();
+    let password = options.password.clone();
+
+    // 创建 VNC 服务器（异步 API，需要在 tokio runtime 中运行）
+    let (vnc_server, mut event_rx) = VncServer::new(width, height, name, password);
+
+    // VNC 服务器的任务在 runtime 的工作线程中运行，监听失败（端口被占用等）时直接返回错误
+    let rt = Runtime::new().map_err(VncError::Runtime)?;
+    let listener = rt
+        .block_on(TcpListener::bind(address))
+        .map_err(|source| VncError::Listen { address, source })?;
+    let address = listener.local_addr().unwrap_or(address);
+    let server = Arc::new(vnc_server);
+
+    // 处理服务器事件；停止时把仍然连接的客户端记为断开
+    let (events_stop, mut stop_rx) = oneshot::channel();
+    let events = rt.spawn(async move {
+        let mut connected = 0usize;
+        loop {
+            tokio::select! {
+                event = event_rx.recv() => match event {
+                    Some(ServerEvent::ClientConnected { id, address }) => {
+                        info!("VNC 客户端 {} ({}) 已连接", id, address);
+                        connected += 1;
+                        clients.connected();
+                    }
+                    Some(ServerEvent::ClientDisconnected { id }) => {
+                        info!("VNC 客户端 {} 已断开", id);
+                        connected = connected.saturating_sub(1);
+                        clients.disconnected();
+                    }
+                    Some(_) => {}
+                    None => break,
+                },
+                _ = &mut stop_rx => break,
+            }
+        }
+        for _ in 0..connected {
+            clients.disconnected();
+        }
+    });
+    let accept = rt.spawn(accept_clients(listener, server.clone()));
+
+    info!("VNC 服务器已启动");
+    info!("VNC 服务器地址: {}", address);
+    info!("使用 VNC 客户端连接:");
+    info!("  vncviewer localhost:{}", address.port());
+    info!("  或者: vncviewer localhost::{}", address.port());
+    if options.password.is_some() {
+        info!("  需要密码认证");
+    }
+    if let Some(crop) = &options.crop {
+        info!("  裁剪区域: {}", crop);
+    }
+    if options.scale.is_some() {
+        info!("  帧缓冲区分辨率: {}x{}", width, height);
+    }
+
+    Ok(VncOutput {
+        server,
+        crop: options.crop,
+        framebuffer,
+        method: options.scale.map(|scale| scale.method).unwrap_or_default(),
+        runtime: Some(rt),
+        accept,
+        events,
+        events_stop: Some(events_stop),
+    })
+}
+
+/// 接受客户端连接，每个连接交给 relay 转发
+async fn accept_clients(listener: TcpListener, server: Arc<VncServer>) {
+    loop {
+        let (client, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("无法接受 VNC 连接: {}", e);
+                continue;
+            }
+        };
+        info!("VNC 连接来自 {}", peer);
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(e) = relay(client, &server).await {
+                error!("VNC 客户端 {} 出错: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// 让服务器反向连接一个只接受一次连接的回环端口，在客户端和服务器之间转发数据，直到任意一方断开
+async fn relay(mut client: TcpStream, server: &VncServer) -> std::io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let port = listener.local_addr()?.port();
+    let forward = tokio::spawn(async move {
+        let (mut inner, _) = listener.accept().await?;
+        tokio::io::copy_bidirectional(&mut client, &mut inner).await?;
+        Ok::<_, std::io::Error>(())
+    });
+    // 反向连接在 VNC 握手（包括密码认证）完成后返回
+    if let Err(e) = server
+        .connect_reverse(Ipv4Addr::LOCALHOST.to_string(), port)
+        .await
+    {
+        forward.abort();
+        return Err(e);
+    }
+    forward.await.map_err(std::io::Error::other)?
+}
+
+/// 把 region 内的画面转换为 RGB888，行之间没有填充
+/// RGB 类格式只需要重排字节；YUV 格式逐像素转换，开销明显更大
+fn to_rgb(frame: &VideoFrameRef<&gst::BufferRef>, region: &CropRect) -> Result<Vec<u8>, VncError> {
+    let (x0, y0) = (region.x as usize, region.y as usize);
+    let (width, height) = (region.width as usize, region.height as usize);
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    let format = frame.format();
+    let strides: Vec<usize> = frame.plane_stride().iter().map(|&s| s as usize).collect();
+
+    // R、G、B 在像素中的位置，以及每个像素的字节数
+    let packed = match format {
+        VideoFormat::Rgbx | VideoFormat::Rgba => Some(([0, 1, 2], 4)),
+        VideoFormat::Bgrx | VideoFormat::Bgra => Some(([2, 1, 0], 4)),
+        VideoFormat::Rgb => Some(([0, 1, 2], 3)),
+        _ => None,
+    };
+    if let Some((order, size)) = packed {
+        let data = frame.plane_data(0).map_err(VncError::Map)?;
+        for row in data.chunks(strides[0]).skip(y0).take(height) {
+            for pixel in row[x0 * size..(x0 + width) * size].chunks_exact(size) {
+                rgb.extend(order.map(|i| pixel[i]));
+            }
+        }
+        return Ok(rgb);
+    }
+
+    if !matches!(format, VideoFormat::Nv12 | VideoFormat::I420) {
+        return Err(VncError::UnsupportedFormat(format));
+    }
+    let planes = (0..frame.n_planes())
+        .map(|plane| frame.plane_data(plane))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(VncError::Map)?;
+    let colorimetry = frame.info().colorimetry();
+    for row in y0..y0 + height {
+        for col in x0..x0 + width {
+            let y = planes[0][row * strides[0] + col];
+            // 色度平面的宽高都是亮度平面的一半
+            let (u, v) = if format == VideoFormat::Nv12 {
+                let i = row / 2 * strides[1] + col / 2 * 2;
+                (planes[1][i], planes[1][i + 1])
+            } else {
+                (
+                    planes[1][row / 2 * strides[1] + col / 2],
+                    planes[2][row / 2 * strides[2] + col / 2],
+                )
+            };
+            rgb.extend(yuv_to_rgb(y, u, v, &colorimetry));
+        }
+    }
+    Ok(rgb)
+}
+
+/// 按 caps 中的色彩矩阵（BT.709 或 BT.601）和取值范围把一个像素转换为 RGB
+fn yuv_to_rgb(y: u8, u: u8, v: u8, colorimetry: &VideoColorimetry) -> [u8; 3] {
+    let (kr, kb) = match colorimetry.matrix() {
+        VideoColorMatrix::Bt709 => (0.2126, 0.0722),
+        _ => (0.299, 0.114),
+    };
+    let (y, u, v) = (y as f32, u as f32 - 128.0, v as f32 - 128.0);
+    let (y, u, v) = match colorimetry.range() {
+        VideoColorRange::Range0_255 => (y, u, v),
+        // 有限范围：Y 为 16~235，UV 为 16~240
+        _ => (
+            (y - 16.0) * 255.0 / 219.0,
+            u * 255.0 / 224.0,
+            v * 255.0 / 224.0,
+        ),
+    };
+    let r = y + 2.0 * (1.0 - kr) * v;
+    let b = y + 2.0 * (1.0 - kb) * u;
+    let g = (y - kr * r - kb * b) / (1.0 - kr - kb);
+    [r, g, b].map(|c| c.round().clamp(0.0, 255.0) as u8)
+}
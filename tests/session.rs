@@ -0,0 +1,63 @@
+//! 在进程内启动合成器的集成测试
+//!
+//! 需要能创建 Wayland 合成器的环境（软件渲染即可），默认忽略，用
+//! `cargo test --test session -- --ignored` 运行。
+
+use futures::StreamExt;
+use weadless::{DisplayThread, Session};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+
+#[test]
+#[ignore = "需要能创建 Wayland 合成器的环境"]
+fn session_frame_and_input() {
+    let mut session = Session::builder()
+        .render_node("software")
+        .size(WIDTH, HEIGHT)
+        .build()
+        .expect("无法启动合成器");
+    assert!(session.wayland_display().is_some());
+
+    let frame = session.frame().expect("取帧失败");
+    assert_eq!(frame.size(), (WIDTH * HEIGHT * 4) as usize);
+
+    session.pointer_motion_absolute(10.0, 20.0);
+    session.pointer_button(0x110, true);
+    session.pointer_button(0x110, false);
+    session.keyboard_input(30, true);
+    session.keyboard_input(30, false);
+
+    // 注入输入后合成器应该仍然能正常出帧
+    let frame = session.frame().expect("注入输入后取帧失败");
+    assert_eq!(frame.size(), (WIDTH * HEIGHT * 4) as usize);
+
+    session.shutdown();
+}
+
+#[test]
+#[ignore = "需要能创建 Wayland 合成器的环境"]
+fn display_thread_stream_ends_after_shutdown() {
+    let display = DisplayThread::spawn(
+        Session::builder()
+            .render_node("software")
+            .size(WIDTH, HEIGHT),
+        2,
+    )
+    .expect("无法启动显示线程");
+    let mut frames = display.subscribe();
+    assert_eq!(display.subscriber_count(), 1);
+
+    display.keyboard_input(30, true);
+    display.keyboard_input(30, false);
+
+    let frame = futures::executor::block_on(frames.next())
+        .expect("帧流提前结束")
+        .expect("订阅者不应落后");
+    assert_eq!(frame.sequence, 0);
+    assert_eq!(frame.buffer.size(), (WIDTH * HEIGHT * 4) as usize);
+
+    display.shutdown();
+    // 显示线程退出后，取完剩余的帧帧流就结束，不会一直等待
+    futures::executor::block_on(frames.count());
+}